@@ -1,4 +1,26 @@
 pub mod chip_8;
 
+mod assembler;
+mod audio;
+mod browser;
+mod config;
+mod controller;
+mod database;
 mod cpu;
 mod display;
+mod filters;
+mod font;
+mod headless;
+mod hotkeys;
+mod image;
+mod keypad_overlay;
+mod loader;
+mod menu;
+mod octo;
+mod osd;
+mod palette;
+mod patch;
+mod recorder;
+mod rpl;
+mod screenshot;
+mod wav;
@@ -0,0 +1,1071 @@
+use std::collections::{HashMap, VecDeque};
+
+// Assembler for Octo, the language Octo cartridges carry their programs in. Like Octo, the
+// program starts at 200 with a jump to `main`, left out when the source begins with `: main`,
+// and labels can be used before they are defined wherever an address is expected.
+const START: usize = 0x200;
+const MAX_SIZE: usize = 0x10000;
+const MAX_EXPANSIONS: usize = 100_000; // Macro and string mode expansions, to stop runaway recursion
+
+pub fn assemble(source: &str) -> Result<Vec<u8>, String> {
+    Assembler::new(tokenize(source)?).run()
+}
+
+#[derive(Clone)]
+struct Token {
+    text: String,
+    line: usize,
+    string: bool, // A "quoted" string, which is never a name or a number
+}
+
+impl Token {
+    fn is(&self, text: &str) -> bool {
+        !self.string && self.text == text
+    }
+}
+
+// Forward references, patched once every label is known
+#[derive(Clone, Copy)]
+enum Fixup {
+    Address,      // Low 12 bits of an instruction
+    Long,         // 16 bit word
+    UnpackHi,     // Low nibble of a 6XNN immediate
+    UnpackLongHi, // Whole 6XNN immediate, the high byte
+    UnpackLo,     // Whole 6XNN immediate, the low byte
+}
+
+struct Macro {
+    params: Vec<String>,
+    body: Vec<Token>,
+    calls: usize,
+}
+
+struct StringMode {
+    alphabet: Vec<char>,
+    body: Vec<Token>,
+}
+
+// Whitespace separated tokens, "#" starting a comment and quotes a string with \ escapes
+fn tokenize(source: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = vec![];
+    for (number, line) in source.lines().enumerate() {
+        let line_number = number + 1;
+        let mut chars = line.chars().peekable();
+        loop {
+            while chars.peek().is_some_and(|c| c.is_whitespace()) {
+                chars.next();
+            }
+            match chars.peek() {
+                None | Some('#') => break,
+                Some('"') => {
+                    chars.next();
+                    let mut text = String::new();
+                    loop {
+                        match chars.next() {
+                            None => return Err(format!("line {}: unterminated string", line_number)),
+                            Some('"') => break,
+                            Some('\\') => text.push(match chars.next() {
+                                Some('n') => '\n',
+                                Some('r') => '\r',
+                                Some('t') => '\t',
+                                Some('v') => '\x0B',
+                                Some('0') => '\0',
+                                Some(c) => c,
+                                None => return Err(format!("line {}: unterminated string", line_number)),
+                            }),
+                            Some(c) => text.push(c),
+                        }
+                    }
+                    tokens.push(Token {
+                        text,
+                        line: line_number,
+                        string: true,
+                    });
+                }
+                Some(_) => {
+                    let mut text = String::new();
+                    while let Some(&c) = chars.peek().filter(|c| !c.is_whitespace()) {
+                        text.push(c);
+                        chars.next();
+                    }
+                    tokens.push(Token {
+                        text,
+                        line: line_number,
+                        string: false,
+                    });
+                }
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+// Decimal, 0x hexadecimal or 0b binary, with an optional minus sign
+fn parse_number(text: &str) -> Option<f64> {
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, text),
+    };
+    let value = if let Some(hex) = digits.strip_prefix("0x").or_else(|| digits.strip_prefix("0X")) {
+        i64::from_str_radix(hex, 16).ok()? as f64
+    } else if let Some(binary) = digits.strip_prefix("0b").or_else(|| digits.strip_prefix("0B")) {
+        i64::from_str_radix(binary, 2).ok()? as f64
+    } else if digits.starts_with(|c: char| c.is_ascii_digit()) {
+        digits.parse().ok()?
+    } else {
+        return None;
+    };
+    Some(if negative { -value } else { value })
+}
+
+fn binary_operator(op: &str, a: f64, b: f64) -> Option<f64> {
+    let (x, y) = (a as i64, b as i64);
+    Some(match op {
+        "-" => a - b,
+        "+" => a + b,
+        "*" => a * b,
+        "/" => a / b,
+        "%" => a % b,
+        "&" => (x & y) as f64,
+        "|" => (x | y) as f64,
+        "^" => (x ^ y) as f64,
+        "<<" => (x << (y & 63)) as f64,
+        ">>" => (x >> (y & 63)) as f64,
+        "pow" => a.powf(b),
+        "min" => a.min(b),
+        "max" => a.max(b),
+        "<" => (a < b) as i64 as f64,
+        "<=" => (a <= b) as i64 as f64,
+        "==" => (a == b) as i64 as f64,
+        "!=" => (a != b) as i64 as f64,
+        ">=" => (a >= b) as i64 as f64,
+        ">" => (a > b) as i64 as f64,
+        _ => return None,
+    })
+}
+
+fn unary_operator(op: &str, a: f64) -> Option<f64> {
+    Some(match op {
+        "-" => -a,
+        "~" => !(a as i64) as f64,
+        "!" => (a == 0.) as i64 as f64,
+        "sin" => a.sin(),
+        "cos" => a.cos(),
+        "tan" => a.tan(),
+        "exp" => a.exp(),
+        "log" => a.ln(),
+        "abs" => a.abs(),
+        "sqrt" => a.sqrt(),
+        "sign" => a.signum(),
+        "ceil" => a.ceil(),
+        "floor" => a.floor(),
+        _ => return None,
+    })
+}
+
+struct Assembler {
+    tokens: VecDeque<Token>,
+    line: usize, // Of the last token taken, for errors
+    rom: Vec<u8>,
+    end: usize, // Past the highest address written
+    here: usize,
+    labels: HashMap<String, usize>,
+    constants: HashMap<String, f64>,
+    aliases: HashMap<String, usize>,
+    fixups: Vec<(usize, Token, Fixup)>,
+    loops: Vec<(usize, Vec<usize>)>, // Start and the exit jumps of its whiles
+    branches: Vec<usize>,            // Jumps of the open begins and elses
+    macros: HashMap<String, Macro>,
+    string_modes: HashMap<String, Vec<StringMode>>,
+    expansions: usize,
+}
+
+impl Assembler {
+    fn new(tokens: Vec<Token>) -> Assembler {
+        let aliases = [("unpack-hi", 0x0), ("unpack-lo", 0x1), ("compare-temp", 0xF)]
+            .iter()
+            .map(|&(name, register)| (name.to_string(), register))
+            .collect();
+        Assembler {
+            tokens: tokens.into(),
+            line: 0,
+            rom: vec![0; START],
+            end: START,
+            here: START,
+            labels: HashMap::new(),
+            constants: HashMap::new(),
+            aliases,
+            fixups: vec![],
+            loops: vec![],
+            branches: vec![],
+            macros: HashMap::new(),
+            string_modes: HashMap::new(),
+            expansions: 0,
+        }
+    }
+
+    fn run(mut self) -> Result<Vec<u8>, String> {
+        let main_first = self.tokens.front().is_some_and(|t| t.is(":"))
+            && self.tokens.get(1).is_some_and(|t| t.is("main"));
+        if !main_first {
+            self.op(0x1000)?;
+        }
+        while let Some(token) = self.tokens.pop_front() {
+            self.line = token.line;
+            self.statement(token)?;
+        }
+        if !self.loops.is_empty() {
+            return Err("a loop has no again".to_string());
+        }
+        if !self.branches.is_empty() {
+            return Err("a begin has no end".to_string());
+        }
+
+        let main = *self.labels.get("main").ok_or("the program has no main label")?;
+        if !main_first {
+            self.patch_jump(START, main)?;
+        }
+        for (at, token, fixup) in std::mem::take(&mut self.fixups) {
+            let value = match (self.labels.get(&token.text), self.constants.get(&token.text)) {
+                (Some(&label), _) => label,
+                (None, Some(&constant)) if constant >= 0. => constant as usize,
+                _ => return Err(format!("line {}: undefined name {}", token.line, token.text)),
+            };
+            match fixup {
+                Fixup::Address if value <= 0xFFF => {
+                    self.rom[at] |= (value >> 8) as u8;
+                    self.rom[at + 1] = value as u8;
+                }
+                Fixup::Long if value <= 0xFFFF => {
+                    self.rom[at] = (value >> 8) as u8;
+                    self.rom[at + 1] = value as u8;
+                }
+                Fixup::UnpackHi => self.rom[at + 1] |= (value >> 8) as u8 & 0xF,
+                Fixup::UnpackLongHi if value <= 0xFFFF => self.rom[at + 1] = (value >> 8) as u8,
+                Fixup::UnpackLo => self.rom[at + 1] = value as u8,
+                _ => return Err(format!("line {}: {} is out of range", token.line, token.text)),
+            }
+        }
+        self.rom.truncate(self.end);
+        Ok(self.rom.split_off(START))
+    }
+
+    fn error(&self, message: &str) -> String {
+        format!("line {}: {}", self.line, message)
+    }
+
+    fn next(&mut self, expected: &str) -> Result<Token, String> {
+        let token = self
+            .tokens
+            .pop_front()
+            .ok_or_else(|| format!("line {}: expected {}, the program ends", self.line, expected))?;
+        self.line = token.line;
+        Ok(token)
+    }
+
+    fn expect(&mut self, text: &str) -> Result<(), String> {
+        let token = self.next(text)?;
+        if token.is(text) {
+            Ok(())
+        } else {
+            Err(self.error(&format!("expected {}, got {}", text, token.text)))
+        }
+    }
+
+    fn peek_is(&self, text: &str) -> bool {
+        self.tokens.front().is_some_and(|token| token.is(text))
+    }
+
+    fn name(&mut self) -> Result<String, String> {
+        let token = self.next("a name")?;
+        if token.string || parse_number(&token.text).is_some() || self.register_of(&token).is_some() {
+            return Err(self.error(&format!("{} is not a valid name", token.text)));
+        }
+        Ok(token.text)
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        let token = self.next("a string")?;
+        if !token.string {
+            return Err(self.error(&format!("expected a string, got {}", token.text)));
+        }
+        Ok(token.text)
+    }
+
+    fn register_of(&self, token: &Token) -> Option<usize> {
+        if token.string {
+            return None;
+        }
+        if let Some(&register) = self.aliases.get(&token.text) {
+            return Some(register);
+        }
+        let digit = token.text.strip_prefix('v').or_else(|| token.text.strip_prefix('V'))?;
+        match digit.len() {
+            1 => usize::from_str_radix(digit, 16).ok(),
+            _ => None,
+        }
+    }
+
+    fn register(&mut self) -> Result<usize, String> {
+        let token = self.next("a register")?;
+        self.register_of(&token)
+            .ok_or_else(|| self.error(&format!("expected a register, got {}", token.text)))
+    }
+
+    fn is_register_next(&self) -> bool {
+        self.tokens.front().is_some_and(|token| self.register_of(token).is_some())
+    }
+
+    fn define_label(&mut self, name: String, address: usize) -> Result<(), String> {
+        if self.labels.contains_key(&name) || self.constants.contains_key(&name) {
+            return Err(self.error(&format!("{} is defined twice", name)));
+        }
+        self.labels.insert(name, address);
+        Ok(())
+    }
+
+    // Tokens up to the matching }, the { having been taken
+    fn block(&mut self) -> Result<Vec<Token>, String> {
+        let mut depth = 0;
+        let mut body = vec![];
+        loop {
+            let token = self.next("}")?;
+            if token.is("{") {
+                depth += 1;
+            } else if token.is("}") {
+                if depth == 0 {
+                    return Ok(body);
+                }
+                depth -= 1;
+            }
+            body.push(token);
+        }
+    }
+
+    fn emit(&mut self, value: u8) -> Result<(), String> {
+        if self.here >= MAX_SIZE {
+            return Err(self.error("the program does not fit in 64 KiB"));
+        }
+        if self.rom.len() <= self.here {
+            self.rom.resize(self.here + 1, 0);
+        }
+        self.rom[self.here] = value;
+        self.here += 1;
+        self.end = self.end.max(self.here);
+        Ok(())
+    }
+
+    fn op(&mut self, opcode: u16) -> Result<(), String> {
+        self.emit((opcode >> 8) as u8)?;
+        self.emit(opcode as u8)
+    }
+
+    fn patch_jump(&mut self, at: usize, target: usize) -> Result<(), String> {
+        if target > 0xFFF {
+            return Err(self.error(&format!("cannot jump to {:04X}, past FFF", target)));
+        }
+        self.rom[at] = 0x10 | (target >> 8) as u8;
+        self.rom[at + 1] = target as u8;
+        Ok(())
+    }
+
+    // A number, a defined name or a { calculation }
+    fn value_of(&mut self, token: &Token) -> Result<f64, String> {
+        if token.is("{") {
+            let expression = self.block()?;
+            return self.calculate(&expression);
+        }
+        self.known_value(token)
+            .ok_or_else(|| self.error(&format!("undefined name {}", token.text)))
+    }
+
+    fn known_value(&self, token: &Token) -> Option<f64> {
+        if token.string {
+            return None;
+        }
+        parse_number(&token.text)
+            .or_else(|| self.constants.get(&token.text).copied())
+            .or_else(|| self.labels.get(&token.text).map(|&label| label as f64))
+    }
+
+    fn value(&mut self) -> Result<f64, String> {
+        let token = self.next("a value")?;
+        self.value_of(&token)
+    }
+
+    // A byte, negative values down to -128 standing for their two's complement
+    fn short_value(&mut self) -> Result<u8, String> {
+        let value = self.value()?.floor() as i64;
+        if !(-128..=255).contains(&value) {
+            return Err(self.error(&format!("{} does not fit in a byte", value)));
+        }
+        Ok(value as u8)
+    }
+
+    fn tiny_value(&mut self) -> Result<u16, String> {
+        let value = self.value()?.floor() as i64;
+        if !(0..=15).contains(&value) {
+            return Err(self.error(&format!("{} does not fit in a nibble", value)));
+        }
+        Ok(value as u16)
+    }
+
+    // An address of at most 12 or 16 bits, or a label that may be defined further on.
+    // Returns the address, 0 when it is patched in later.
+    fn address(&mut self, fixup: Fixup, at: usize) -> Result<usize, String> {
+        let token = self.next("an address")?;
+        let max = match fixup {
+            Fixup::Address => 0xFFF,
+            _ => 0xFFFF,
+        };
+        let is_name = !token.string && !token.is("{") && self.known_value(&token).is_none();
+        if is_name {
+            self.fixups.push((at, token, fixup));
+            return Ok(0);
+        }
+        let value = self.value_of(&token)?.floor() as i64;
+        if !(0..=max).contains(&value) {
+            return Err(self.error(&format!("address {:X} is out of range", value)));
+        }
+        Ok(value as usize)
+    }
+
+    fn op_address(&mut self, opcode: u16) -> Result<(), String> {
+        let address = self.address(Fixup::Address, self.here)?;
+        self.op(opcode | address as u16)
+    }
+
+    fn statement(&mut self, token: Token) -> Result<(), String> {
+        if token.string {
+            return Err(self.error(&format!("unexpected string \"{}\"", token.text)));
+        }
+        match token.text.as_str() {
+            ":" => {
+                let name = self.name()?;
+                self.define_label(name, self.here)?;
+            }
+            ":next" => {
+                let name = self.name()?;
+                self.define_label(name, self.here + 1)?;
+            }
+            ":alias" => {
+                let name = self.name()?;
+                let register = if self.peek_is("{") {
+                    let token = self.next("{")?;
+                    self.value_of(&token)? as usize
+                } else {
+                    self.register()?
+                };
+                if register > 0xF {
+                    return Err(self.error(&format!("{} is not a register", register)));
+                }
+                self.aliases.insert(name, register);
+            }
+            ":const" => {
+                let name = self.name()?;
+                let value = self.value()?;
+                self.constants.insert(name, value);
+            }
+            ":calc" => {
+                let name = self.name()?;
+                self.expect("{")?;
+                let expression = self.block()?;
+                let value = self.calculate(&expression)?;
+                self.constants.insert(name, value);
+            }
+            ":org" => {
+                let address = self.value()?.floor() as i64;
+                if !(0..MAX_SIZE as i64).contains(&address) {
+                    return Err(self.error(&format!("cannot place code at {:X}", address)));
+                }
+                self.here = address as usize;
+            }
+            ":byte" => {
+                let value = self.short_value()?;
+                self.emit(value)?;
+            }
+            ":pointer" => {
+                let address = self.address(Fixup::Long, self.here)?;
+                self.op(address as u16)?;
+            }
+            ":call" => self.op_address(0x2000)?,
+            ":unpack" => {
+                let (hi, lo) = (self.aliases["unpack-hi"] as u16, self.aliases["unpack-lo"] as u16);
+                let nibble = if self.peek_is("long") {
+                    self.next("long")?;
+                    None
+                } else {
+                    Some(self.tiny_value()?)
+                };
+                let at = self.here;
+                let fixups = self.fixups.len();
+                let fixup = match nibble {
+                    Some(_) => Fixup::UnpackHi,
+                    None => Fixup::UnpackLongHi,
+                };
+                let address = self.address(fixup, at)? as u16;
+                if self.fixups.len() > fixups {
+                    let token = self.fixups[fixups].1.clone();
+                    self.fixups.push((at + 2, token, Fixup::UnpackLo));
+                }
+                let high = match nibble {
+                    Some(nibble) => nibble << 4 | (address >> 8 & 0xF),
+                    None => address >> 8,
+                };
+                self.op(0x6000 | hi << 8 | high)?;
+                self.op(0x6000 | lo << 8 | (address & 0xFF))?;
+            }
+            ":macro" => {
+                let name = self.name()?;
+                let mut params = vec![];
+                loop {
+                    let token = self.next("{")?;
+                    if token.is("{") {
+                        break;
+                    }
+                    params.push(token.text);
+                }
+                let body = self.block()?;
+                self.macros.insert(name, Macro { params, body, calls: 0 });
+            }
+            ":stringmode" => {
+                let name = self.name()?;
+                let alphabet = self.string()?.chars().collect();
+                self.expect("{")?;
+                let body = self.block()?;
+                self.string_modes.entry(name).or_default().push(StringMode { alphabet, body });
+            }
+            ":assert" => {
+                let message = if self.tokens.front().is_some_and(|token| token.string) {
+                    self.string()?
+                } else {
+                    "assertion failed".to_string()
+                };
+                if self.value()? == 0. {
+                    return Err(self.error(&message));
+                }
+            }
+            // Debugger directives, there is no debugger
+            ":breakpoint" | ":proto" => {
+                self.next("a name")?;
+            }
+            ":monitor" => {
+                self.next("an address")?;
+                self.next("a length or format")?;
+            }
+            "return" | ";" => self.op(0x00EE)?,
+            "clear" => self.op(0x00E0)?,
+            "scroll-right" => self.op(0x00FB)?,
+            "scroll-left" => self.op(0x00FC)?,
+            "exit" => self.op(0x00FD)?,
+            "lores" => self.op(0x00FE)?,
+            "hires" => self.op(0x00FF)?,
+            "scroll-down" => {
+                let rows = self.tiny_value()?;
+                self.op(0x00C0 | rows)?;
+            }
+            "scroll-up" => {
+                let rows = self.tiny_value()?;
+                self.op(0x00D0 | rows)?;
+            }
+            "audio" => self.op(0xF002)?,
+            "plane" => {
+                let plane = self.tiny_value()?;
+                self.op(0xF001 | plane << 8)?;
+            }
+            "bcd" => self.op_register(0xF033)?,
+            "saveflags" => self.op_register(0xF075)?,
+            "loadflags" => self.op_register(0xF085)?,
+            "save" | "load" => {
+                let x = self.register()? as u16;
+                if self.peek_is("-") {
+                    self.next("-")?;
+                    let y = self.register()? as u16;
+                    let n = if token.text == "save" { 2 } else { 3 };
+                    self.op(0x5000 | x << 8 | y << 4 | n)?;
+                } else {
+                    let nn = if token.text == "save" { 0x55 } else { 0x65 };
+                    self.op(0xF000 | x << 8 | nn)?;
+                }
+            }
+            "sprite" => {
+                let x = self.register()? as u16;
+                let y = self.register()? as u16;
+                let n = self.tiny_value()?;
+                self.op(0xD000 | x << 8 | y << 4 | n)?;
+            }
+            "jump" => self.op_address(0x1000)?,
+            "jump0" => self.op_address(0xB000)?,
+            "native" => self.op_address(0x0000)?,
+            "delay" | "buzzer" | "pitch" => {
+                self.expect(":=")?;
+                let nn = match token.text.as_str() {
+                    "delay" => 0x15,
+                    "buzzer" => 0x18,
+                    _ => 0x3A,
+                };
+                self.op_register(0xF000 | nn)?;
+            }
+            "i" => self.index_statement()?,
+            "loop" => self.loops.push((self.here, vec![])),
+            "while" => {
+                if self.loops.is_empty() {
+                    return Err(self.error("while outside of a loop"));
+                }
+                let condition = self.condition()?;
+                self.emit_condition(&condition, false)?;
+                let exit = self.here;
+                self.op(0x1000)?;
+                if let Some((_, exits)) = self.loops.last_mut() {
+                    exits.push(exit);
+                }
+            }
+            "again" => {
+                let (start, exits) = self.loops.pop().ok_or_else(|| self.error("again without a loop"))?;
+                self.op(0x1000)?;
+                self.patch_jump(self.here - 2, start)?;
+                for exit in exits {
+                    self.patch_jump(exit, self.here)?;
+                }
+            }
+            "if" => {
+                let condition = self.condition()?;
+                let token = self.next("then or begin")?;
+                if token.is("then") {
+                    self.emit_condition(&condition, true)?;
+                } else if token.is("begin") {
+                    self.emit_condition(&condition, false)?;
+                    self.branches.push(self.here);
+                    self.op(0x1000)?;
+                } else {
+                    return Err(self.error(&format!("expected then or begin, got {}", token.text)));
+                }
+            }
+            "else" => {
+                let branch = self.branches.pop().ok_or_else(|| self.error("else without a begin"))?;
+                let jump = self.here;
+                self.op(0x1000)?;
+                self.patch_jump(branch, self.here)?;
+                self.branches.push(jump);
+            }
+            "end" => {
+                let branch = self.branches.pop().ok_or_else(|| self.error("end without a begin"))?;
+                self.patch_jump(branch, self.here)?;
+            }
+            _ if self.register_of(&token).is_some() => {
+                let x = self.register_of(&token).unwrap_or_default();
+                self.register_statement(x)?;
+            }
+            _ if self.macros.contains_key(&token.text) => self.expand_macro(&token.text)?,
+            _ if self.string_modes.contains_key(&token.text) => self.expand_string_mode(&token.text)?,
+            _ if self.known_value(&token).is_some() || token.is("{") => {
+                let value = self.value_of(&token)?.floor() as i64;
+                if !(-128..=255).contains(&value) {
+                    return Err(self.error(&format!("{} does not fit in a byte", value)));
+                }
+                self.emit(value as u8)?;
+            }
+            _ => {
+                // A subroutine call, possibly to a label defined further on
+                self.tokens.push_front(token);
+                self.op_address(0x2000)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn op_register(&mut self, opcode: u16) -> Result<(), String> {
+        let x = self.register()? as u16;
+        self.op(opcode | x << 8)
+    }
+
+    fn index_statement(&mut self) -> Result<(), String> {
+        let operator = self.next(":= or +=")?;
+        if operator.is("+=") {
+            return self.op_register(0xF01E);
+        }
+        if !operator.is(":=") {
+            return Err(self.error(&format!("expected := or +=, got {}", operator.text)));
+        }
+        if self.peek_is("hex") || self.peek_is("bighex") {
+            let nn = if self.next("hex")?.is("hex") { 0x29 } else { 0x30 };
+            return self.op_register(0xF000 | nn);
+        }
+        if self.peek_is("long") {
+            self.next("long")?;
+            self.op(0xF000)?;
+            let address = self.address(Fixup::Long, self.here)?;
+            return self.op(address as u16);
+        }
+        self.op_address(0xA000)
+    }
+
+    fn register_statement(&mut self, x: usize) -> Result<(), String> {
+        let x16 = (x as u16) << 8;
+        let operator = self.next("an operator")?;
+        let logic = match operator.text.as_str() {
+            "|=" => Some(0x1),
+            "&=" => Some(0x2),
+            "^=" => Some(0x3),
+            "=-" => Some(0x7),
+            ">>=" => Some(0x6),
+            "<<=" => Some(0xE),
+            _ => None,
+        };
+        if let Some(n) = logic.filter(|_| !operator.string) {
+            let y = self.register()? as u16;
+            return self.op(0x8000 | x16 | y << 4 | n);
+        }
+        if operator.is(":=") {
+            if self.peek_is("random") {
+                self.next("random")?;
+                let nn = self.short_value()? as u16;
+                return self.op(0xC000 | x16 | nn);
+            }
+            if self.peek_is("key") || self.peek_is("delay") {
+                let nn = if self.next("key")?.is("key") { 0x0A } else { 0x07 };
+                return self.op(0xF000 | x16 | nn);
+            }
+        }
+        let (register_op, value_op): (u16, fn(u8) -> u16) = if operator.is(":=") {
+            (0x0, |nn| 0x6000 | nn as u16)
+        } else if operator.is("+=") {
+            (0x4, |nn| 0x7000 | nn as u16)
+        } else if operator.is("-=") {
+            (0x5, |nn| 0x7000 | nn.wrapping_neg() as u16)
+        } else {
+            return Err(self.error(&format!("unknown operator {}", operator.text)));
+        };
+        if self.is_register_next() {
+            let y = self.register()? as u16;
+            self.op(0x8000 | x16 | y << 4 | register_op)
+        } else {
+            let nn = self.short_value()?;
+            self.op(value_op(nn) | x16)
+        }
+    }
+
+    fn condition(&mut self) -> Result<(usize, String, Option<Operand>), String> {
+        let x = self.register()?;
+        let operator = self.next("a comparison")?;
+        if operator.is("key") || operator.is("-key") {
+            return Ok((x, operator.text, None));
+        }
+        if !["==", "!=", "<", ">", "<=", ">="].iter().any(|op| operator.is(op)) {
+            return Err(self.error(&format!("unknown comparison {}", operator.text)));
+        }
+        let operand = if self.is_register_next() {
+            Operand::Register(self.register()?)
+        } else {
+            Operand::Value(self.short_value()?)
+        };
+        Ok((x, operator.text, Some(operand)))
+    }
+
+    // Emits instructions that skip the next one when the condition holds, or when it does
+    // not for `negated`, as `if ... then` needs
+    fn emit_condition(&mut self, condition: &(usize, String, Option<Operand>), negated: bool) -> Result<(), String> {
+        let (x, operator, operand) = condition;
+        let x = *x as u16;
+        let operator = match (negated, operator.as_str()) {
+            (false, operator) => operator,
+            (true, "==") => "!=",
+            (true, "!=") => "==",
+            (true, "key") => "-key",
+            (true, "-key") => "key",
+            (true, ">") => "<=",
+            (true, "<") => ">=",
+            (true, ">=") => "<",
+            (true, _) => ">",
+        };
+        match (operator, operand) {
+            ("key", _) => self.op(0xE09E | x << 8),
+            ("-key", _) => self.op(0xE0A1 | x << 8),
+            ("==", Some(Operand::Register(y))) => self.op(0x5000 | x << 8 | (*y as u16) << 4),
+            ("==", Some(Operand::Value(nn))) => self.op(0x3000 | x << 8 | *nn as u16),
+            ("!=", Some(Operand::Register(y))) => self.op(0x9000 | x << 8 | (*y as u16) << 4),
+            ("!=", Some(Operand::Value(nn))) => self.op(0x4000 | x << 8 | *nn as u16),
+            (_, Some(operand)) => {
+                // The other operand goes to compare-temp, which is subtracted from VX or
+                // VX from it, leaving VF set when nothing was borrowed
+                let t = self.aliases["compare-temp"] as u16;
+                match operand {
+                    Operand::Register(y) => self.op(0x8000 | t << 8 | (*y as u16) << 4)?,
+                    Operand::Value(nn) => self.op(0x6000 | t << 8 | *nn as u16)?,
+                }
+                let (subtract, no_borrow) = match operator {
+                    ">" => (0x5, false),
+                    "<" => (0x7, false),
+                    ">=" => (0x7, true),
+                    _ => (0x5, true),
+                };
+                self.op(0x8000 | t << 8 | x << 4 | subtract)?;
+                self.op(0x3F00 | no_borrow as u16)
+            }
+            _ => Err(self.error("incomplete comparison")),
+        }
+    }
+
+    fn expand_macro(&mut self, name: &str) -> Result<(), String> {
+        self.count_expansion()?;
+        let params = self.macros[name].params.clone();
+        let mut args = HashMap::new();
+        for param in params {
+            let arg = self.next(&format!("an argument for {}", name))?;
+            args.insert(param, arg);
+        }
+        let line = self.line;
+        let mac = self.macros.get_mut(name).ok_or("undefined macro")?;
+        let calls = mac.calls;
+        mac.calls += 1;
+        let body: Vec<Token> = mac
+            .body
+            .iter()
+            .map(|token| match args.get(&token.text).filter(|_| !token.string) {
+                Some(arg) => Token { line, ..arg.clone() },
+                None if token.is("CALLS") => Token {
+                    text: calls.to_string(),
+                    line,
+                    string: false,
+                },
+                None => Token { line, ..token.clone() },
+            })
+            .collect();
+        for token in body.into_iter().rev() {
+            self.tokens.push_front(token);
+        }
+        Ok(())
+    }
+
+    // Expands the body of the mode's alphabet holding each character, with CHAR its code,
+    // INDEX its position in the text and VALUE its position in the alphabet
+    fn expand_string_mode(&mut self, name: &str) -> Result<(), String> {
+        let text = self.string()?;
+        let line = self.line;
+        let mut expansion = vec![];
+        for (index, c) in text.chars().enumerate() {
+            self.count_expansion()?;
+            let (value, body) = self.string_modes[name]
+                .iter()
+                .find_map(|mode| mode.alphabet.iter().position(|&a| a == c).map(|value| (value, &mode.body)))
+                .ok_or_else(|| self.error(&format!("string mode {} has no character {:?}", name, c)))?;
+            for token in body {
+                let substitute = match token.text.as_str() {
+                    _ if token.string => None,
+                    "CHAR" => Some(c as u32 as usize),
+                    "INDEX" => Some(index),
+                    "VALUE" => Some(value),
+                    _ => None,
+                };
+                expansion.push(match substitute {
+                    Some(number) => Token {
+                        text: number.to_string(),
+                        line,
+                        string: false,
+                    },
+                    None => Token { line, ..token.clone() },
+                });
+            }
+        }
+        for token in expansion.into_iter().rev() {
+            self.tokens.push_front(token);
+        }
+        Ok(())
+    }
+
+    fn count_expansion(&mut self) -> Result<(), String> {
+        self.expansions += 1;
+        if self.expansions > MAX_EXPANSIONS {
+            return Err(self.error("macros expand without end"));
+        }
+        Ok(())
+    }
+
+    // Octo's calculations have no precedence, operators apply right to left:
+    // { 1 + 2 * 3 } is 7 and { 2 * 3 + 1 } is 8
+    fn calculate(&self, tokens: &[Token]) -> Result<f64, String> {
+        let mut at = 0;
+        let value = self.expression(tokens, &mut at)?;
+        match tokens.get(at) {
+            None => Ok(value),
+            Some(token) => Err(self.error(&format!("unexpected {} in calculation", token.text))),
+        }
+    }
+
+    fn expression(&self, tokens: &[Token], at: &mut usize) -> Result<f64, String> {
+        let left = self.term(tokens, at)?;
+        match tokens.get(*at) {
+            Some(token) if !token.string && binary_operator(&token.text, 0., 1.).is_some() => {
+                *at += 1;
+                let right = self.expression(tokens, at)?;
+                Ok(binary_operator(&token.text, left, right).unwrap_or_default())
+            }
+            _ => Ok(left),
+        }
+    }
+
+    fn term(&self, tokens: &[Token], at: &mut usize) -> Result<f64, String> {
+        let token = tokens.get(*at).ok_or_else(|| self.error("incomplete calculation"))?;
+        *at += 1;
+        if token.string {
+            return Err(self.error(&format!("unexpected string \"{}\" in calculation", token.text)));
+        }
+        match token.text.as_str() {
+            "(" => {
+                let value = self.expression(tokens, at)?;
+                match tokens.get(*at) {
+                    Some(token) if token.is(")") => {
+                        *at += 1;
+                        Ok(value)
+                    }
+                    _ => Err(self.error("missing ) in calculation")),
+                }
+            }
+            "strlen" => match tokens.get(*at) {
+                Some(token) if token.string => {
+                    *at += 1;
+                    Ok(token.text.chars().count() as f64)
+                }
+                _ => Err(self.error("strlen needs a string")),
+            },
+            "@" => {
+                let address = self.term(tokens, at)?.floor() as usize;
+                Ok(self.rom.get(address).copied().unwrap_or(0) as f64)
+            }
+            "HERE" => Ok(self.here as f64),
+            "PI" => Ok(std::f64::consts::PI),
+            "E" => Ok(std::f64::consts::E),
+            operator if unary_operator(operator, 0.).is_some() => {
+                let value = self.term(tokens, at)?;
+                Ok(unary_operator(operator, value).unwrap_or_default())
+            }
+            _ => self
+                .known_value(token)
+                .ok_or_else(|| self.error(&format!("undefined name {}", token.text))),
+        }
+    }
+}
+
+enum Operand {
+    Register(usize),
+    Value(u8),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bytes(source: &str) -> Vec<u8> {
+        assemble(source).unwrap_or_else(|e| panic!("{}: {}", source, e))
+    }
+
+    #[test]
+    fn labels_and_forward_references() {
+        let source = ": main i := data sub jump main : sub return : data 0x12 0x34";
+        assert_eq!(bytes(source), [0xA2, 0x08, 0x22, 0x06, 0x12, 0x00, 0x00, 0xEE, 0x12, 0x34]);
+        // Without main first, a jump to it comes first
+        assert_eq!(bytes(": data 1 : main jump data"), [0x12, 0x03, 0x01, 0x12, 0x02]);
+        assert_eq!(assemble(": main\njump nowhere"), Err("line 2: undefined name nowhere".to_string()));
+        assert_eq!(assemble("clear"), Err("the program has no main label".to_string()));
+    }
+
+    #[test]
+    fn unpack() {
+        let source = ": main :unpack 0xA later :unpack long later :unpack 1 0x345 : later";
+        assert_eq!(bytes(source), [0x60, 0xA2, 0x61, 0x0C, 0x60, 0x02, 0x61, 0x0C, 0x60, 0x13, 0x61, 0x45]);
+    }
+
+    #[test]
+    fn loops() {
+        let source = ": main loop v0 += 1 while v0 != 3 again";
+        assert_eq!(bytes(source), [0x70, 0x01, 0x40, 0x03, 0x12, 0x08, 0x12, 0x00]);
+        assert!(assemble(": main loop").is_err());
+    }
+
+    #[test]
+    fn conditionals() {
+        let source = ": main if v0 == 1 then v1 := 2 if v0 key begin clear else v1 := 3 end";
+        let expected = [
+            0x40, 0x01, 0x61, 0x02, // if ... then skips the body unless v0 == 1
+            0xE0, 0x9E, 0x12, 0x0C, // if ... begin jumps to else unless the key is down
+            0x00, 0xE0, 0x12, 0x0E, // the body jumps past else
+            0x61, 0x03,
+        ];
+        assert_eq!(bytes(source), expected);
+        assert!(assemble(": main if v0 == 1 begin").is_err());
+    }
+
+    // `then` skips the body when the comparison is false, comparing through VF
+    #[test]
+    fn comparisons() {
+        let cases: [(&str, &[u8]); 11] = [
+            ("v1 == v2", &[0x91, 0x20]),
+            ("v1 != v2", &[0x51, 0x20]),
+            ("v1 == 7", &[0x41, 0x07]),
+            ("v1 != 7", &[0x31, 0x07]),
+            ("v1 key", &[0xE1, 0xA1]),
+            ("v1 -key", &[0xE1, 0x9E]),
+            ("v1 > v2", &[0x8F, 0x20, 0x8F, 0x15, 0x3F, 0x01]),
+            ("v1 < v2", &[0x8F, 0x20, 0x8F, 0x17, 0x3F, 0x01]),
+            ("v1 >= v2", &[0x8F, 0x20, 0x8F, 0x17, 0x3F, 0x00]),
+            ("v1 <= v2", &[0x8F, 0x20, 0x8F, 0x15, 0x3F, 0x00]),
+            ("v1 > 7", &[0x6F, 0x07, 0x8F, 0x15, 0x3F, 0x01]),
+        ];
+        for (condition, expected) in cases.iter() {
+            assert_eq!(bytes(&format!(": main if {} then", condition)), *expected, "{}", condition);
+        }
+    }
+
+    #[test]
+    fn macros() {
+        assert_eq!(bytes(":macro m A { A := CALLS } : main m v1 m v2"), [0x12, 0x02, 0x61, 0x00, 0x62, 0x01]);
+        assert!(assemble(":macro m { m } : main m").is_err());
+    }
+
+    #[test]
+    fn string_modes() {
+        let source = ":stringmode s \"ab\" { VALUE INDEX CHAR } : main s \"ba\"";
+        assert_eq!(bytes(source), [0x12, 0x02, 0x01, 0x00, 0x62, 0x00, 0x01, 0x61]);
+    }
+
+    // Operators apply right to left without precedence
+    #[test]
+    fn calculations() {
+        let source = ":calc a { 1 + 2 * 3 } :calc b { 2 * 3 + 1 } :calc c { ( 2 * 3 ) + 1 }
+                      : main :byte a :byte b :byte c :byte { 0x10 << 2 } :byte { -1 + 2 } :byte { HERE & 0xFF }";
+        assert_eq!(bytes(source), [0x12, 0x02, 0x07, 0x08, 0x07, 0x40, 0x01, 0x07]);
+    }
+
+    // A GIF whose pixels carry the payload two bits each, as Octo saves them
+    fn write_cartridge(filename: &std::path::Path, json: &str) {
+        let mut payload = (json.len() as u32).to_be_bytes().to_vec();
+        payload.extend_from_slice(json.as_bytes());
+        let mut pixels: Vec<u8> = payload
+            .iter()
+            .flat_map(|&byte| (0..4).rev().map(move |pair| byte >> (pair * 2) & 3))
+            .collect();
+        let width = 32;
+        pixels.resize(pixels.len().div_ceil(width) * width, 0);
+        let height = pixels.len() / width;
+        let palette = [0, 0, 0, 85, 85, 85, 170, 170, 170, 255, 255, 255];
+        let file = std::fs::File::create(filename).unwrap();
+        let mut encoder = gif::Encoder::new(file, width as u16, height as u16, &palette).unwrap();
+        let frame = gif::Frame::from_indexed_pixels(width as u16, height as u16, &pixels, None);
+        encoder.write_frame(&frame).unwrap();
+    }
+
+    #[test]
+    fn cartridge() {
+        let filename = std::env::temp_dir().join(format!("ivsemu-test-{}.gif", std::process::id()));
+        let json = r#"{"options": {"tickrate": 20, "shiftQuirks": true},
+                       "program": ": main\n  v0 := 1 # comment\n  loop again\n"}"#;
+        write_cartridge(&filename, json);
+        let cartridge = crate::chip_8::octo::read_cartridge(filename.to_str().unwrap());
+        std::fs::remove_file(&filename).unwrap();
+
+        let cartridge = cartridge.unwrap();
+        assert_eq!(bytes(&cartridge.program), [0x60, 0x01, 0x12, 0x02]);
+        assert_eq!(crate::chip_8::octo::get_clock_hz(&cartridge.options), Some(1200.));
+        assert_eq!(crate::chip_8::octo::get_quirks(&cartridge.options), [("shift", true)]);
+    }
+}
@@ -0,0 +1,257 @@
+use sdl2::audio::{AudioCallback, AudioDevice, AudioSpecDesired};
+
+pub const SAMPLE_RATE: i32 = 44100;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Waveform {
+    Square,
+    Triangle,
+    Sawtooth,
+    Sine,
+}
+
+impl Waveform {
+    pub fn from_name(name: &str) -> Option<Waveform> {
+        match name {
+            "square" => Some(Waveform::Square),
+            "triangle" => Some(Waveform::Triangle),
+            "sawtooth" => Some(Waveform::Sawtooth),
+            "sine" => Some(Waveform::Sine),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct Tone {
+    pub frequency: f32,
+    pub volume: f32,
+    pub waveform: Waveform,
+}
+
+impl Tone {
+    pub fn new() -> Tone {
+        Tone {
+            frequency: 440.,
+            volume: 0.25,
+            waveform: Waveform::Square,
+        }
+    }
+}
+
+pub struct Oscillator {
+    tone: Tone,
+    sample_rate: f32,
+    phase: f32,
+    pub playing: bool,
+}
+
+impl Oscillator {
+    pub fn new(tone: Tone, sample_rate: i32) -> Oscillator {
+        Oscillator {
+            tone,
+            sample_rate: sample_rate as f32,
+            phase: 0.,
+            playing: false,
+        }
+    }
+
+    pub fn next_sample(&mut self) -> f32 {
+        if !self.playing {
+            self.phase = 0.;
+            return 0.;
+        }
+        let value = match self.tone.waveform {
+            Waveform::Square => {
+                if self.phase < 0.5 {
+                    1.
+                } else {
+                    -1.
+                }
+            }
+            Waveform::Triangle => 1. - 4. * (self.phase - 0.5).abs(),
+            Waveform::Sawtooth => 2. * self.phase - 1.,
+            Waveform::Sine => (self.phase * 2. * std::f32::consts::PI).sin(),
+        };
+        self.phase = (self.phase + self.tone.frequency / self.sample_rate) % 1.;
+        value * self.tone.volume
+    }
+}
+
+impl AudioCallback for Oscillator {
+    type Channel = f32;
+
+    fn callback(&mut self, out: &mut [f32]) {
+        for sample in out.iter_mut() {
+            *sample = self.next_sample();
+        }
+    }
+}
+
+pub trait Beeper {
+    fn set_playing(&mut self, playing: bool);
+    fn toggle_mute(&mut self) -> bool;
+}
+
+pub struct SdlBeeper {
+    device: AudioDevice<Oscillator>,
+    muted: bool,
+}
+
+impl SdlBeeper {
+    pub fn init(sdl_context: &sdl2::Sdl, tone: Tone) -> Result<SdlBeeper, String> {
+        let desired_spec = AudioSpecDesired {
+            freq: Some(SAMPLE_RATE),
+            channels: Some(1),
+            samples: Some(512),
+        };
+        let device = sdl_context
+            .audio()?
+            .open_playback(None, &desired_spec, |spec| Oscillator::new(tone, spec.freq))?;
+        device.resume();
+
+        Ok(SdlBeeper {
+            device,
+            muted: false,
+        })
+    }
+}
+
+impl Beeper for SdlBeeper {
+    fn set_playing(&mut self, playing: bool) {
+        let playing = playing && !self.muted;
+        let mut oscillator = self.device.lock();
+        if oscillator.playing != playing {
+            oscillator.playing = playing;
+        }
+    }
+
+    fn toggle_mute(&mut self) -> bool {
+        self.muted = !self.muted;
+        self.muted
+    }
+}
+
+// Renders one emulated frame of samples at a time, independent of wall time
+pub struct BufferBeeper {
+    oscillator: Oscillator,
+    frame_rate: u32,
+    remainder: u32,
+    muted: bool,
+    pub samples: Vec<f32>,
+}
+
+impl BufferBeeper {
+    pub fn new(tone: Tone, frame_rate: u32) -> BufferBeeper {
+        BufferBeeper {
+            oscillator: Oscillator::new(tone, SAMPLE_RATE),
+            frame_rate,
+            remainder: 0,
+            muted: false,
+            samples: vec![],
+        }
+    }
+
+    pub fn render_frame(&mut self) {
+        // Carry the fractional part so every second holds exactly SAMPLE_RATE samples
+        let total = SAMPLE_RATE as u32 + self.remainder;
+        let count = total / self.frame_rate;
+        self.remainder = total % self.frame_rate;
+        for _ in 0..count {
+            let sample = self.oscillator.next_sample();
+            self.samples.push(sample);
+        }
+    }
+}
+
+impl Beeper for BufferBeeper {
+    fn set_playing(&mut self, playing: bool) {
+        self.oscillator.playing = playing && !self.muted;
+    }
+
+    fn toggle_mute(&mut self) -> bool {
+        self.muted = !self.muted;
+        self.muted
+    }
+}
+
+// Used when no audio device is available
+pub struct SilentBeeper {
+    muted: bool,
+}
+
+impl SilentBeeper {
+    pub fn new() -> SilentBeeper {
+        SilentBeeper { muted: false }
+    }
+}
+
+impl Beeper for SilentBeeper {
+    fn set_playing(&mut self, _playing: bool) {}
+
+    fn toggle_mute(&mut self) -> bool {
+        self.muted = !self.muted;
+        self.muted
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A period of 100 samples, which does not divide the 735 samples of a frame at 60 Hz:
+    // the second frame starts 35 samples into a period rather than at its beginning
+    fn tone() -> Tone {
+        Tone {
+            frequency: 441.,
+            volume: 0.5,
+            waveform: Waveform::Square,
+        }
+    }
+
+    #[test]
+    fn samples_per_frame() {
+        let mut beeper = BufferBeeper::new(tone(), 60);
+        beeper.render_frame();
+        assert_eq!(beeper.samples.len(), 735);
+
+        // 44100 / 64 leaves a fraction, carried until a second holds every sample
+        let mut beeper = BufferBeeper::new(tone(), 64);
+        let mut counts = vec![];
+        for _ in 0..64 {
+            let before = beeper.samples.len();
+            beeper.render_frame();
+            counts.push(beeper.samples.len() - before);
+        }
+        assert!(counts.iter().all(|&count| count == 689 || count == 690));
+        assert_eq!(beeper.samples.len(), SAMPLE_RATE as usize);
+    }
+
+    #[test]
+    fn phase_carries_across_frames() {
+        let mut beeper = BufferBeeper::new(tone(), 60);
+        beeper.set_playing(true);
+        beeper.render_frame();
+        beeper.render_frame();
+
+        let mut oscillator = Oscillator::new(tone(), SAMPLE_RATE);
+        oscillator.playing = true;
+        let expected: Vec<f32> = (0..beeper.samples.len()).map(|_| oscillator.next_sample()).collect();
+        assert_eq!(beeper.samples, expected);
+        assert_eq!(beeper.samples[752..760], [-0.5; 8]);
+    }
+
+    #[test]
+    fn mute_renders_silence() {
+        let mut beeper = BufferBeeper::new(tone(), 60);
+        assert!(beeper.toggle_mute());
+        beeper.set_playing(true);
+        beeper.render_frame();
+        assert!(beeper.samples.iter().all(|&sample| sample == 0.));
+
+        assert!(!beeper.toggle_mute());
+        beeper.set_playing(true);
+        beeper.render_frame();
+        assert!(beeper.samples[735..].iter().any(|&sample| sample != 0.));
+    }
+}
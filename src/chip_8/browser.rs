@@ -0,0 +1,252 @@
+use super::database::Database;
+use super::display::Display;
+use super::font;
+use super::loader;
+
+use sdl2::keyboard::Keycode;
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+pub const ROM_EXTENSIONS: [&str; 5] = ["ch8", "c8x", "sc8", "xo8", "gif"]; // GIFs being Octo cartridges
+
+const PAGE_LINES: usize = 10;
+
+const BACKGROUND_COLOR: Color = Color::RGBA(0, 0, 0, 220);
+const SELECTED_COLOR: Color = Color::RGBA(230, 160, 0, 230);
+const TEXT_COLOR: Color = Color::RGB(255, 255, 255);
+const DIRECTORY_COLOR: Color = Color::RGB(150, 150, 150);
+
+pub fn is_rom(path: &Path) -> bool {
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| ROM_EXTENSIONS.contains(&extension.to_lowercase().as_str()))
+}
+
+// "Brix [Andreas Gustafsson, 1990].ch8" becomes "Brix [Andreas Gustafsson, 1990]", for ROMs
+// the database does not know
+fn rom_title(path: &Path) -> String {
+    path.file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+#[derive(PartialEq)]
+enum Kind {
+    Dir,
+    Archive, // Opened like a directory
+    Rom,
+}
+
+struct Entry {
+    title: String,
+    path: PathBuf, // For ROMs in an archive, the name given to the loader
+    kind: Kind,
+}
+
+// Lists the subdirectories, ZIP archives and ROMs of a directory, the emulation is stopped while it is open
+pub struct Browser {
+    pub open: bool,
+    dir: PathBuf,
+    archive: Option<PathBuf>, // Archive being listed, inside dir
+    entries: Vec<Entry>,
+    selected: usize,
+    error: Option<String>,
+    database: Database,
+    titles: HashMap<String, (SystemTime, String)>, // By ROM name, so listing again only reads changed files
+}
+
+impl Browser {
+    pub fn new(dir: &str, database: Database) -> Browser {
+        Browser {
+            open: false,
+            dir: PathBuf::from(dir),
+            archive: None,
+            entries: vec![],
+            selected: 0,
+            error: None,
+            database,
+            titles: HashMap::new(),
+        }
+    }
+
+    // The database title of a ROM given as the loader takes it, standard input being unknown
+    pub fn get_title(&self, name: &str) -> String {
+        let known = match loader::get_file(name) {
+            Some(_) if !self.database.is_empty() => loader::read_rom(name).ok(),
+            _ => None,
+        };
+        known
+            .and_then(|rom| self.database.get_title(&rom).map(|title| title.to_string()))
+            .unwrap_or_else(|| rom_title(Path::new(name)))
+    }
+
+    fn cached_title(&mut self, name: &str) -> String {
+        let modified = loader::get_file(name)
+            .and_then(|file| std::fs::metadata(file).and_then(|metadata| metadata.modified()).ok());
+        let modified = match modified {
+            Some(modified) => modified,
+            None => return self.get_title(name),
+        };
+        match self.titles.get(name) {
+            Some((time, title)) if *time == modified => title.clone(),
+            _ => {
+                let title = self.get_title(name);
+                self.titles.insert(name.to_string(), (modified, title.clone()));
+                title
+            }
+        }
+    }
+
+    pub fn show(&mut self) {
+        self.open = true;
+        self.refresh();
+    }
+
+    fn refresh(&mut self) {
+        self.entries.clear();
+        self.selected = 0;
+        self.error = None;
+        if let Ok(dir) = self.dir.canonicalize() {
+            self.dir = dir;
+        }
+        if let Some(archive) = self.archive.clone() {
+            self.entries.push(Entry {
+                title: "..".to_string(),
+                path: self.dir.clone(),
+                kind: Kind::Dir,
+            });
+            match loader::list_archive(&archive.to_string_lossy()) {
+                Ok(roms) => {
+                    let entries: Vec<Entry> = roms
+                        .into_iter()
+                        .map(|rom| {
+                            let name = format!("{}#{}", archive.display(), rom);
+                            Entry {
+                                title: self.cached_title(&name),
+                                path: PathBuf::from(name),
+                                kind: Kind::Rom,
+                            }
+                        })
+                        .collect();
+                    self.entries.extend(entries);
+                }
+                Err(e) => self.error = Some(e.to_string()),
+            }
+            return;
+        }
+        if let Some(parent) = self.dir.parent() {
+            self.entries.push(Entry {
+                title: "..".to_string(),
+                path: parent.to_path_buf(),
+                kind: Kind::Dir,
+            });
+        }
+        let read_dir = match std::fs::read_dir(&self.dir) {
+            Ok(read_dir) => read_dir,
+            Err(e) => {
+                self.error = Some(e.to_string());
+                return;
+            }
+        };
+        let mut dirs = vec![];
+        let mut roms = vec![];
+        for entry in read_dir.filter_map(|entry| entry.ok()) {
+            let path = entry.path();
+            let hidden = entry.file_name().to_string_lossy().starts_with('.');
+            if path.is_dir() && !hidden {
+                dirs.push(Entry {
+                    title: format!("{}/", entry.file_name().to_string_lossy()),
+                    path,
+                    kind: Kind::Dir,
+                });
+            } else if path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("zip")) {
+                dirs.push(Entry {
+                    title: format!("{}/", entry.file_name().to_string_lossy()),
+                    path,
+                    kind: Kind::Archive,
+                });
+            } else if is_rom(&path) {
+                roms.push(Entry {
+                    title: self.cached_title(&path.to_string_lossy()),
+                    path,
+                    kind: Kind::Rom,
+                });
+            }
+        }
+        dirs.sort_by_key(|entry| entry.title.to_lowercase());
+        roms.sort_by_key(|entry| entry.title.to_lowercase());
+        self.entries.extend(dirs);
+        self.entries.extend(roms);
+    }
+
+    // Returns the ROM chosen with a key press, if any
+    pub fn handle_key(&mut self, keycode: Keycode) -> Option<PathBuf> {
+        let last = self.entries.len().saturating_sub(1);
+        match keycode {
+            Keycode::Up => self.selected = self.selected.saturating_sub(1),
+            Keycode::Down => self.selected = (self.selected + 1).min(last),
+            Keycode::PageUp => self.selected = self.selected.saturating_sub(PAGE_LINES),
+            Keycode::PageDown => self.selected = (self.selected + PAGE_LINES).min(last),
+            Keycode::Home => self.selected = 0,
+            Keycode::End => self.selected = last,
+            Keycode::Backspace | Keycode::Left => {
+                if self.archive.take().is_none() {
+                    self.dir = self.dir.parent().unwrap_or(&self.dir).to_path_buf();
+                }
+                self.refresh();
+            }
+            Keycode::Return | Keycode::KpEnter | Keycode::Right => {
+                let entry = self.entries.get(self.selected)?;
+                match entry.kind {
+                    Kind::Rom => return Some(entry.path.clone()),
+                    Kind::Archive => self.archive = Some(entry.path.clone()),
+                    Kind::Dir => {
+                        self.archive = None;
+                        self.dir = entry.path.clone();
+                    }
+                }
+                self.refresh();
+            }
+            Keycode::Escape => self.open = false,
+            _ => {}
+        }
+        None
+    }
+
+    pub fn draw(&self, display: &mut Display) {
+        let (window_width, window_height) = display.window_size();
+        let scale = (window_height / 240).clamp(1, 3);
+        let line_height = (font::GLYPH_HEIGHT * scale * 3 / 2) as i32;
+        let margin = (font::GLYPH_WIDTH * scale) as i32;
+        display.fill_rect(Rect::new(0, 0, window_width, window_height), BACKGROUND_COLOR);
+
+        let title = format!("Open ROM: {}", self.archive.as_ref().unwrap_or(&self.dir).display());
+        display.draw_text(margin, margin, scale, &title, TEXT_COLOR);
+        let top = margin + 2 * line_height;
+        if let Some(error) = &self.error {
+            display.draw_text(margin, top, scale, error, TEXT_COLOR);
+            return;
+        }
+        if self.entries.iter().all(|entry| entry.kind != Kind::Rom) {
+            let help = format!("No {} files here", ROM_EXTENSIONS.join("/"));
+            display.draw_text(margin, window_height as i32 - margin - line_height, scale, &help, DIRECTORY_COLOR);
+        }
+
+        // Scroll so the selected entry stays in view
+        let visible = ((window_height as i32 - top - margin) / line_height).max(1) as usize;
+        let first = (self.selected + 1).saturating_sub(visible);
+        let mut y = top;
+        for (i, entry) in self.entries.iter().enumerate().skip(first).take(visible) {
+            if i == self.selected {
+                let bar = Rect::new(margin / 2, y - line_height / 6, window_width - margin as u32, line_height as u32);
+                display.fill_rect(bar, SELECTED_COLOR);
+            }
+            let color = if entry.kind == Kind::Rom { TEXT_COLOR } else { DIRECTORY_COLOR };
+            display.draw_text(margin, y, scale, &entry.title, color);
+            y += line_height;
+        }
+    }
+}
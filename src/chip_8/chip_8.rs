@@ -1,84 +1,676 @@
-use super::cpu::cpu::CPU;
-use super::display::Display;
+use super::audio::{Beeper, SdlBeeper, SilentBeeper};
+use super::browser::Browser;
+use super::config::Config;
+use super::controller::Controllers;
+use super::database::Database;
+use super::cpu::cpu::{State, CPU, FRAMES_PER_SECOND};
+use super::cpu::platform::Platform;
+use super::display::{Display, Region, HEIGHT, WIDTH};
+use super::filters::Filter;
+use super::headless;
+use super::hotkeys::Action;
+use super::keypad_overlay::KeypadOverlay;
+use super::loader::{self, LoadError};
+use super::menu::{self, Menu};
+use super::osd::Osd;
+use super::recorder::Recorder;
+use super::rpl::{self, RplFlags};
+use super::screenshot;
 
 use sdl2::event::Event;
-use sdl2::keyboard::Keycode;
-use std::io::Read;
+use sdl2::keyboard::Scancode;
+use std::time::{Duration, Instant, SystemTime};
+
+const MAX_CATCH_UP_FRAMES: u32 = 4;
+const STATE_SLOTS: usize = 4;
+const WATCH_INTERVAL: Duration = Duration::from_millis(500);
 
 pub fn run() {
-    let mut cpu = CPU::new();
-    load_rom(
-        "src/chip_8/roms/Trip8 Demo (2008) [Revival Studios].ch8",
-        &mut cpu,
-    );
+    let mut config = match Config::from_args() {
+        Ok(config) => config,
+        Err(message) => {
+            println!("{}", message);
+            return;
+        }
+    };
+
+    if config.show_flags.is_some() || config.clear_flags.is_some() {
+        manage_rpl_flags(&config);
+        return;
+    }
+
+    let mut cpu = match create_cpu(&config) {
+        Ok(cpu) => cpu,
+        Err(e) => {
+            println!("Unable to load ROM: {}", e);
+            return;
+        }
+    };
+
+    match config.headless {
+        Some(_) if config.rom.is_none() => println!("A ROM is needed to run headless"),
+        Some(frames) => headless::run(&config, &mut cpu, frames),
+        None => run_window(&mut config, &mut cpu),
+    }
+
+    if let Some(prefix) = &config.coverage {
+        match cpu.export_coverage(prefix) {
+            Ok(()) => println!("Coverage map written to {}.txt/.png/.pbm", prefix),
+            Err(e) => println!("Unable to write coverage map: {}", e),
+        }
+    }
+}
+
+// Prints or deletes saved RPL flags, ROMs being named by their file name as in profile sections
+fn manage_rpl_flags(config: &Config) {
+    let rom_name = |name: &str| match std::path::Path::new(name).file_name() {
+        Some(file_name) if name != "all" => file_name.to_string_lossy().into_owned(),
+        _ => name.to_string(),
+    };
+    if let Some(name) = &config.show_flags {
+        let name = rom_name(name);
+        let roms: Vec<(String, [u8; rpl::SIZE])> =
+            rpl::list().into_iter().filter(|(rom, _)| name == "all" || *rom == name).collect();
+        if roms.is_empty() {
+            println!("No RPL flags saved for {}", name);
+        }
+        for (rom, flags) in roms {
+            let hex: Vec<String> = flags.iter().map(|flag| format!("{:02X}", flag)).collect();
+            println!("{}: {}", rom, hex.join(" "));
+        }
+    }
+    if let Some(name) = &config.clear_flags {
+        let name = rom_name(name);
+        match rpl::clear(&name) {
+            Ok(cleared) if cleared.is_empty() => println!("No RPL flags saved for {}", name),
+            Ok(cleared) => println!("Cleared the RPL flags of {}", cleared.join(", ")),
+            Err(e) => println!("Unable to clear the RPL flags: {}", e),
+        }
+    }
+}
+
+// A CPU set up from the config, with the ROM loaded when there is one
+fn create_cpu(config: &Config) -> Result<CPU, LoadError> {
+    let mut cpu = CPU::new(config.platform);
+    cpu.set_clock(config.clock_hz);
+    cpu.set_quirks(config.quirks);
+    cpu.set_machine_code(config.get_machine_code());
+    cpu.set_font(config.get_font(), config.get_font_address());
+    cpu.set_palette(config.get_palette());
+    cpu.set_persistence(config.persistence);
+    for (key_index, scancodes) in config.key_bindings.iter() {
+        cpu.bind_key(*key_index, scancodes);
+    }
+    if let (Platform::Vip, Some(interpreter)) = (config.platform, &config.vip_interpreter) {
+        let monitor = config.vip_monitor.as_deref().map(loader::read_vip_image).transpose()?;
+        cpu.boot_vip(&loader::read_vip_image(interpreter)?, monitor.as_deref());
+    }
+    if let Some(rom) = &config.rom {
+        let mut patches = if config.auto_patch { loader::find_patches(rom) } else { vec![] };
+        patches.retain(|patch| !config.patches.contains(patch));
+        patches.extend(config.patches.iter().cloned());
+        loader::load_rom(rom, &patches, &mut cpu)?;
+        cpu.set_rpl_flags(RplFlags::open(&config.get_rom_name()));
+    }
+    Ok(cpu)
+}
+
+// Window state shared by the hotkeys, the pause menu and the ROM browser
+struct Frontend<'a> {
+    config: &'a mut Config,
+    display: Display,
+    beeper: Box<dyn Beeper>,
+    controllers: Option<Controllers>,
+    recorder: Option<Recorder>,
+    keypad_overlay: KeypadOverlay,
+    menu: Menu,
+    browser: Browser,
+    paused: bool,
+    muted: bool,
+    redraw: bool,              // Upload the whole frame, e.g. when the filter changes
+    rebinding: Option<usize>,  // CHIP-8 key waiting for a host key
+    states: Vec<Option<State>>, // In-memory save state slots
+    slot: usize,
+    osd: Osd,
+    rom_modified: Option<SystemTime>, // Modification time of the loaded ROM file
+    title: String,                    // Of the loaded ROM, from the database when it is known
+    last_watch: Instant,
+}
+
+impl<'a> Frontend<'a> {
+    fn is_running(&self) -> bool {
+        !self.paused && !self.menu.open && !self.browser.open && self.config.rom.is_some()
+    }
+
+    // Replaces the running program with a fresh CPU, applying the ROM's profile
+    fn open_rom(&mut self, cpu: &mut CPU, filename: &str) {
+        if !loader::is_loadable(filename) {
+            self.osd.show(format!("Not a ROM: {}", filename));
+            return;
+        }
+        let config = match self.config.with_rom(filename) {
+            Ok(config) => config,
+            Err(e) => {
+                self.osd.show(e);
+                return;
+            }
+        };
+        match create_cpu(&config) {
+            Ok(new_cpu) => {
+                *cpu = new_cpu;
+                *self.config = config;
+                if let Some(controllers) = &mut self.controllers {
+                    controllers.set_map(self.config.controller.clone());
+                }
+                self.menu.open = false;
+                self.browser.open = false;
+                self.rom_loaded();
+                self.osd.show(format!("Loaded {}", self.rom_title()));
+            }
+            Err(e) => self.osd.show(format!("Unable to load ROM: {}", e)),
+        }
+    }
+
+    // Save states belong to the previous program
+    fn rom_loaded(&mut self) {
+        self.states = vec![None; STATE_SLOTS];
+        self.paused = self.config.pause_on_load;
+        self.redraw = true;
+        self.rom_modified = self.get_rom_modified();
+        self.title = match &self.config.rom {
+            Some(rom) => self.browser.get_title(rom),
+            None => "no ROM".to_string(),
+        };
+        if self.paused {
+            let keys = self.config.hotkeys.describe(Action::Pause);
+            self.osd.show(format!("Paused at the first instruction, {} to run", keys));
+        }
+    }
+
+    fn rom_title(&self) -> String {
+        self.title.clone()
+    }
+
+    fn get_rom_modified(&self) -> Option<SystemTime> {
+        let file = loader::get_file(self.config.rom.as_ref()?)?;
+        std::fs::metadata(file).and_then(|metadata| metadata.modified()).ok()
+    }
+
+    // Hard resets into the new version of the ROM when its file changes on disk
+    fn watch_rom(&mut self, cpu: &mut CPU) {
+        if !self.config.watch || self.last_watch.elapsed() < WATCH_INTERVAL {
+            return;
+        }
+        self.last_watch = Instant::now();
+        let modified = self.get_rom_modified();
+        if modified.is_none() || modified == self.rom_modified {
+            return;
+        }
+        self.rom_modified = modified;
+        let title = self.rom_title();
+        // Built again like open_rom does since a cartridge carries its own options.
+        // Failures are most likely a partly written file, the next write triggers another attempt.
+        let config = match self.config.reload() {
+            Ok(config) => config,
+            Err(e) => {
+                self.osd.show(format!("Unable to reload {}: {}", title, e));
+                return;
+            }
+        };
+        match create_cpu(&config) {
+            Ok(new_cpu) => {
+                *cpu = new_cpu;
+                *self.config = config;
+                if let Some(controllers) = &mut self.controllers {
+                    controllers.set_map(self.config.controller.clone());
+                }
+                self.rom_loaded();
+                self.osd.show(format!("Reloaded {}", title));
+            }
+            Err(e) => self.osd.show(format!("Unable to reload {}: {}", title, e)),
+        }
+    }
+
+    // Returns false when the action asks to quit
+    fn perform(&mut self, cpu: &mut CPU, action: Action) -> bool {
+        match action {
+            Action::Quit => return false,
+            Action::Reset => cpu.reset_rom(),
+            Action::SpeedUp | Action::SpeedDown => {
+                if action == Action::SpeedUp {
+                    cpu.increase_clock();
+                } else {
+                    cpu.decrease_clock();
+                }
+                if let Err(e) = self.config.remember("clock", &cpu.get_clock_hz().to_string()) {
+                    self.osd.show(e);
+                }
+                self.osd.show(format!(
+                    "Clock {} Hz, {:.1} instructions per frame",
+                    cpu.get_clock_hz(),
+                    cpu.instructions_per_frame()
+                ));
+            }
+            Action::Pause => {
+                self.paused = !self.paused;
+                self.osd.show(if self.paused { "Paused" } else { "Resumed" }.to_string());
+            }
+            Action::Menu => self.menu.toggle(),
+            Action::OpenRom => {
+                self.menu.open = false;
+                if self.browser.open {
+                    self.browser.open = false;
+                } else {
+                    self.browser.show();
+                }
+            }
+            Action::SaveState => {
+                self.states[self.slot] = Some(cpu.save_state());
+                self.osd.show(format!("State saved to slot {}", self.slot + 1));
+            }
+            Action::LoadState => match &self.states[self.slot] {
+                Some(state) => {
+                    cpu.load_state(state);
+                    self.osd.show(format!("State loaded from slot {}", self.slot + 1));
+                }
+                None => self.osd.show(format!("Slot {} is empty", self.slot + 1)),
+            },
+            Action::NextSlot => {
+                self.slot = (self.slot + 1) % self.states.len();
+                self.osd.show(format!("State slot {}", self.slot + 1));
+            }
+            Action::Screenshot => {
+                match screenshot::capture(cpu, self.display.get_scale(), &self.config.screenshot_dir) {
+                    Ok(base) => self.osd.show(format!("Screenshot saved to {}.png", base)),
+                    Err(e) => self.osd.show(format!("Unable to save screenshot: {}", e)),
+                }
+            }
+            Action::Record => match self.recorder.take() {
+                Some(r) => {
+                    let filename = r.filename.clone();
+                    match r.stop() {
+                        Ok(()) => self.osd.show(format!("Recording saved to {}", filename)),
+                        Err(e) => self.osd.show(format!("Unable to finish {}: {}", filename, e)),
+                    }
+                }
+                None => {
+                    let filename = std::path::Path::new(&self.config.screenshot_dir)
+                        .join(format!(
+                            "ivsemu-{}.{}",
+                            screenshot::timestamp(),
+                            self.config.record_format
+                        ))
+                        .to_string_lossy()
+                        .into_owned();
+                    self.start_recording(&filename);
+                }
+            },
+            Action::Palette => {
+                let name = self.config.next_palette().name.clone();
+                if let Err(e) = self.config.remember("palette", &name) {
+                    self.osd.show(e);
+                }
+                self.osd.show(format!("Palette: {}", name));
+                cpu.set_palette(self.config.get_palette());
+            }
+            Action::Persistence => {
+                let persistence = self.config.persistence.next(self.config.phosphor_frames);
+                if let Err(e) = self.config.remember("persistence", persistence.name()) {
+                    self.osd.show(e);
+                }
+                self.osd.show(format!("Persistence: {}", self.config.persistence.name()));
+                cpu.set_persistence(self.config.persistence);
+            }
+            Action::Filter => {
+                let filter = self.config.filter.next();
+                if let Err(e) = self.config.remember("filter", filter.name()) {
+                    self.osd.show(e);
+                }
+                self.osd.show(format!("Filter: {}", self.config.filter.name()));
+                self.redraw = true;
+            }
+            Action::Fullscreen => {
+                self.display.toggle_fullscreen();
+            }
+            Action::IntegerScaling => {
+                self.display.integer_scaling = !self.display.integer_scaling;
+                self.osd.show(format!("Integer scaling {}", on_off(self.display.integer_scaling)));
+            }
+            Action::Keypad => self.keypad_overlay.toggle(cpu),
+            Action::StatusLine => self.osd.status_line = !self.osd.status_line,
+            Action::RebindKeys => {
+                self.menu.open = false;
+                self.osd.show("Rebinding keys, press Escape to stop".to_string());
+                self.osd.show("Press the key for 0x0".to_string());
+                self.rebinding = Some(0x0);
+            }
+            Action::Mute => {
+                self.muted = self.beeper.toggle_mute();
+                self.osd.show(format!("Sound {}", if self.muted { "muted" } else { "unmuted" }));
+            }
+        }
+        true
+    }
 
+    fn start_recording(&mut self, filename: &str) {
+        match Recorder::start(filename, self.config.record_scale, self.config.tone) {
+            Ok(recorder) => {
+                self.recorder = Some(recorder);
+                self.osd.show(format!("Recording to {}", filename));
+            }
+            Err(e) => self.osd.show(format!("Unable to record to {}: {}", filename, e)),
+        }
+    }
+
+    // Current value of the setting an action changes, shown in the pause menu
+    fn setting(&self, cpu: &CPU, action: Action) -> Option<String> {
+        match action {
+            Action::SpeedUp => Some(format!("{} Hz", cpu.get_clock_hz())),
+            Action::Palette => Some(self.config.get_palette().name.clone()),
+            Action::Filter => Some(self.config.filter.name().to_string()),
+            Action::Persistence => Some(self.config.persistence.name().to_string()),
+            Action::IntegerScaling => Some(on_off(self.display.integer_scaling).to_string()),
+            Action::Fullscreen => Some(on_off(self.display.is_fullscreen()).to_string()),
+            Action::Keypad => Some(on_off(self.keypad_overlay.visible).to_string()),
+            Action::Mute => Some(on_off(!self.muted).to_string()),
+            Action::NextSlot => Some(format!("{}", self.slot + 1)),
+            Action::StatusLine => Some(on_off(self.osd.status_line).to_string()),
+            _ => None,
+        }
+    }
+
+    fn status(&self, cpu: &CPU) -> String {
+        let mut status = format!(
+            "{:.0} FPS  {:.1} IPF  {}",
+            self.osd.get_fps(),
+            cpu.instructions_per_frame(),
+            self.rom_title()
+        );
+        if !self.is_running() {
+            status.push_str("  PAUSED");
+        }
+        status
+    }
+
+    fn draw(&mut self, cpu: &mut CPU, dirty: &[Region]) {
+        if self.redraw || (self.config.filter != Filter::None && !dirty.is_empty()) {
+            let (image, width, height) = self.config.filter.apply(cpu.get_frame_buffer(), WIDTH, HEIGHT);
+            self.display.draw(&image, width, height);
+        } else if !dirty.is_empty() {
+            self.display.update(cpu.get_frame_buffer(), dirty);
+        }
+        self.redraw = false;
+
+        self.display.render();
+        if self.keypad_overlay.visible {
+            self.keypad_overlay.draw(&mut self.display, &cpu.get_key_status());
+        }
+        let status = self.status(cpu);
+        self.osd.draw(&mut self.display, &status);
+        if self.menu.open {
+            let values: Vec<Option<String>> = menu::ITEMS
+                .iter()
+                .map(|(_, action, _)| self.setting(cpu, *action))
+                .collect();
+            self.menu.draw(&mut self.display, &self.config.hotkeys, &values);
+        }
+        if self.browser.open {
+            self.browser.draw(&mut self.display);
+        }
+        self.display.present();
+    }
+}
+
+fn on_off(value: bool) -> &'static str {
+    if value {
+        "on"
+    } else {
+        "off"
+    }
+}
+
+fn run_window(config: &mut Config, cpu: &mut CPU) {
     let sdl_context = sdl2::init().unwrap();
-    let mut display = Display::init(&sdl_context);
+    let display = Display::init(
+        &sdl_context,
+        config.vsync,
+        config.integer_scaling,
+        config.fullscreen,
+    );
     let mut event_pump = sdl_context.event_pump().unwrap();
+    let beeper: Box<dyn Beeper> = match SdlBeeper::init(&sdl_context, config.tone) {
+        Ok(beeper) => Box::new(beeper),
+        Err(e) => {
+            println!("Unable to open audio device, running without sound: {}", e);
+            Box::new(SilentBeeper::new())
+        }
+    };
+
+    let controllers = match Controllers::init(&sdl_context, config.controller.clone()) {
+        Ok(controllers) => Some(controllers),
+        Err(e) => {
+            println!("Unable to initialise game controllers: {}", e);
+            None
+        }
+    };
+
+    let keypad_overlay = KeypadOverlay::new(config.keypad_overlay);
+    let config_status_line = config.status_line;
+    let config_rom_dir = config.rom_dir.clone();
+    let database = match &config.rom_database {
+        Some(filename) => Database::load(filename).unwrap_or_else(|e| {
+            println!("Unable to load the ROM database, showing file names: {}", e);
+            Database::new()
+        }),
+        None => Database::new(),
+    };
+
+    let mut frontend = Frontend {
+        config,
+        display,
+        beeper,
+        controllers,
+        recorder: None,
+        keypad_overlay,
+        menu: Menu::new(),
+        browser: Browser::new(&config_rom_dir, database),
+        paused: false,
+        muted: false,
+        redraw: true,
+        rebinding: None,
+        states: vec![None; STATE_SLOTS],
+        slot: 0,
+        osd: Osd::new(config_status_line),
+        rom_modified: None,
+        title: "no ROM".to_string(),
+        last_watch: Instant::now(),
+    };
+    if frontend.config.rom.is_none() {
+        frontend.browser.show();
+    } else {
+        frontend.rom_loaded();
+    }
+    if let Some(filename) = frontend.config.record.clone() {
+        frontend.start_recording(&filename);
+    }
+
+    let frame_duration = Duration::from_secs_f64(1. / FRAMES_PER_SECOND as f64);
+    let mut next_frame = Instant::now();
+    let mut dirty = vec![];
 
     'runner: loop {
         for event in event_pump.poll_iter() {
-            match event {
-                Event::Quit { .. } => break 'runner,
-                Event::KeyDown {
-                    keycode: Some(Keycode::Escape),
-                    ..
-                } => break 'runner,
-                Event::KeyDown {
-                    keycode: Some(Keycode::RightBracket),
+            if let Some(key_index) = frontend.rebinding {
+                if let Event::KeyDown {
+                    scancode: Some(scancode),
                     ..
-                } => {
-                    cpu.increase_clock(true);
+                } = event
+                {
+                    frontend.rebinding = rebind_key(cpu, &mut frontend.osd, frontend.config, key_index, scancode);
+                    continue;
                 }
-                Event::KeyDown {
-                    keycode: Some(Keycode::LeftBracket),
-                    ..
-                } => {
-                    cpu.decrease_clock(true);
+            }
+            // Connections and releases get through while the browser or the menu is open,
+            // so controllers plugged in meanwhile work and keys held when it opened don't stick
+            match event {
+                Event::ControllerDeviceAdded { .. }
+                | Event::ControllerDeviceRemoved { .. }
+                | Event::ControllerButtonUp { .. } => {
+                    if let Some(controllers) = &mut frontend.controllers {
+                        controllers.handle_event(&event, cpu);
+                    }
+                    continue;
                 }
-                Event::KeyDown {
-                    keycode: Some(Keycode::Backspace),
+                Event::KeyUp {
+                    scancode: Some(scancode),
                     ..
                 } => {
-                    cpu.reset_rom();
+                    if let Some(key_index) = cpu.compute_scancode(scancode) {
+                        cpu.release_key(key_index);
+                    }
+                    continue;
                 }
-                Event::KeyDown {
-                    keycode: Some(keycode),
-                    ..
-                } => {
-                    if let Some(key_index) = cpu.compute_keycode(keycode) {
-                        cpu.press_key(key_index);
+                _ => {}
+            }
+            if let Event::DropFile { filename, .. } = &event {
+                frontend.open_rom(cpu, filename);
+                continue;
+            }
+            if frontend.browser.open {
+                match event {
+                    Event::Quit { .. } => break 'runner,
+                    Event::KeyDown {
+                        keycode: Some(keycode),
+                        ..
+                    } => {
+                        if frontend.config.hotkeys.get(keycode) == Some(Action::OpenRom) {
+                            frontend.browser.open = false;
+                        } else if let Some(path) = frontend.browser.handle_key(keycode) {
+                            frontend.open_rom(cpu, &path.to_string_lossy());
+                        }
                     }
+                    _ => {}
                 }
-                Event::KeyUp {
-                    keycode: Some(keycode),
-                    ..
+                continue;
+            }
+            if frontend.menu.open {
+                match event {
+                    Event::Quit { .. } => break 'runner,
+                    Event::KeyDown {
+                        keycode: Some(keycode),
+                        ..
+                    } => {
+                        let action = match frontend.config.hotkeys.get(keycode) {
+                            Some(Action::Menu) => Some(Action::Menu),
+                            _ => frontend.menu.handle_key(keycode),
+                        };
+                        if let Some(action) = action {
+                            if !frontend.perform(cpu, action) {
+                                break 'runner;
+                            }
+                        }
+                    }
+                    _ => {}
+                }
+                continue;
+            }
+            if let Some(controllers) = &mut frontend.controllers {
+                if controllers.handle_event(&event, cpu) {
+                    continue;
+                }
+            }
+            let window_size = frontend.display.window_size();
+            if frontend.keypad_overlay.handle_event(&event, cpu, window_size) {
+                continue;
+            }
+            match event {
+                Event::Quit { .. } => break 'runner,
+                Event::KeyDown {
+                    keycode, scancode, ..
                 } => {
-                    if let Some(key_index) = cpu.compute_keycode(keycode) {
-                        cpu.release_key(key_index);
+                    // The keypad goes by position and comes first, so that a hotkey letter
+                    // typed by a keypad key on another layout (o on Dvorak's S) stays a keypad key
+                    if let Some(key_index) = scancode.and_then(|scancode| cpu.compute_scancode(scancode)) {
+                        cpu.press_key(key_index);
+                    } else if let Some(action) = keycode.and_then(|keycode| frontend.config.hotkeys.get(keycode)) {
+                        if !frontend.perform(cpu, action) {
+                            break 'runner;
+                        }
                     }
                 }
                 _ => {}
             }
         }
 
-        cpu.tick_delay_timer();
-        cpu.tick_sound_timer();
-        if cpu.tick() {
-            cpu.fetch();
-            cpu.decode();
-            if cpu.should_redraw {
-                display.draw(&cpu.get_frame_buffer());
-                cpu.should_redraw = false;
+        frontend.watch_rom(cpu);
+
+        let now = Instant::now();
+        if now < next_frame {
+            std::thread::sleep(next_frame - now);
+            continue;
+        }
+
+        // Emulate every frame that is due, then present once
+        if frontend.is_running() {
+            let mut frames = 0;
+            while Instant::now() >= next_frame && frames < MAX_CATCH_UP_FRAMES {
+                cpu.run_frame();
+                frontend.osd.count_frame();
+                dirty.extend(cpu.render_frame_buffer());
+                if let Some(r) = &mut frontend.recorder {
+                    if let Err(e) = r.capture_frame(cpu) {
+                        frontend.osd.show(format!("Recording stopped: {}", e));
+                        frontend.recorder = None;
+                    }
+                }
+                next_frame += frame_duration;
+                frames += 1;
             }
         }
+        if Instant::now() >= next_frame {
+            next_frame = Instant::now() + frame_duration; // Too far behind or paused, skip ahead
+        }
+        frontend.beeper.set_playing(frontend.is_running() && cpu.is_sound_playing());
+
+        dirty.extend(cpu.render_frame_buffer()); // Palette, persistence or state changed by an action
+        frontend.draw(cpu, &dirty);
+        dirty.clear();
+    }
+
+    if let Some(recorder) = frontend.recorder {
+        recorder.finish();
     }
 }
 
-fn load_rom(filename: &str, cpu: &mut CPU) {
-    let mut f = std::fs::File::open(&filename).expect("no file found");
-    let metadata = std::fs::metadata(&filename).expect("unable to read metadata");
-    let mut buffer = vec![0; metadata.len() as usize];
-    f.read(&mut buffer).expect("buffer overflow");
-    cpu.load_rom(&buffer);
+// Binds the pressed key and returns the next CHIP-8 key to rebind, if any
+// The binding also goes into the config, which a reload or another ROM rebuilds the CPU from
+fn rebind_key(
+    cpu: &mut CPU,
+    osd: &mut Osd,
+    config: &mut Config,
+    key_index: usize,
+    scancode: Scancode,
+) -> Option<usize> {
+    if scancode != Scancode::Escape {
+        cpu.bind_key(key_index, &[scancode]);
+        if let Err(e) = config.remember(&format!("key-{:x}", key_index), scancode.name()) {
+            osd.show(e);
+        }
+        if key_index < 0xF {
+            osd.show(format!("Press the key for 0x{:X}", key_index + 1));
+            return Some(key_index + 1);
+        }
+    }
+
+    osd.show("Key bindings printed to the console".to_string());
+    println!("Key bindings, add these to ivsemu.cfg to keep them:");
+    for key_index in 0x0..0x10 {
+        let names: Vec<&str> = cpu
+            .get_key_bindings(key_index)
+            .iter()
+            .map(|scancode| scancode.name())
+            .collect();
+        println!("key-{:x} = {}", key_index, names.join(","));
+    }
+    None
 }
@@ -0,0 +1,509 @@
+use super::audio::{Tone, Waveform};
+use super::controller::ControllerMap;
+use super::cpu::fonts::{self, SystemFont};
+use super::cpu::platform::Platform;
+use super::cpu::quirks::{Quirks, QUIRK_NAMES};
+use super::display::PersistenceMode;
+use super::filters::Filter;
+use super::hotkeys::{Action, Hotkeys};
+use super::loader;
+use super::octo;
+use super::palette::Palette;
+
+use sdl2::keyboard::{Keycode, Scancode};
+
+const DEFAULT_CONFIG: &str = "ivsemu.cfg";
+
+const USAGE: &str = "usage: ivsemu [ROM] [options], without a ROM the ROM browser opens
+  ROM                 a CHIP-8 program, an Octo cartridge .gif, a .hex dump, - for standard input,
+                      or a .zip archive holding one ROM or followed by #NAME to pick one
+  --config FILE       read options from FILE, one `option = value` per line (default ivsemu.cfg);
+                      options after a `[ROM file name]` line only apply to that ROM
+  --rom-dir DIR       directory the ROM browser starts in, opened with F4 (default .)
+  --rom-database FILE programs.json of the CHIP-8 program database, for the titles of known ROMs
+  --watch on|off      reload the ROM with a hard reset when its file changes (default on)
+  --patch FILE        apply an IPS or BPS patch to the ROM, may be given several times
+  --auto-patch on|off apply game.ips and game.bps found next to game.ch8 first (default on)
+  --pause-on-load on|off  stay paused at the first instruction after loading a ROM (default off)
+  --headless FRAMES   run FRAMES emulated frames (60 per second) without a window
+  --wav FILE          write the beeper output of a headless run to FILE
+  --clock HZ          instructions per second (default 60)
+  --quirk-NAME on|off instructions interpreters disagree on: shift (8XY6/8XYE shift VX),
+                      load-store (FX55/FX65 leave I, default on), jump (BXNN adds VX), logic
+                      (8XY1-3 clear VF), clip (sprites clip at the edges, default on), vblank
+                      (DXYN waits for the next frame) or vf-order (VX wins over VF); Octo
+                      cartridges set them too
+  --platform NAME     memory layout and instructions: chip8, eti660 (programs at 600),
+                      dream6800 (2 KiB RAM), chip8x (programs at 300, color) or vip, which
+                      emulates the whole COSMAC VIP; default chip8 or chip8x for .c8x files
+  --machine-code on|off  run 0NNN calls as 1802 machine code, which can overwrite any of RAM
+                      (default on for eti660, chip8x and vip, off otherwise)
+  --vip-interpreter FILE  the VIP's 512 byte CHIP-8 interpreter, needed by --platform vip
+  --vip-monitor FILE  the VIP's 512 byte monitor ROM, a stand-in providing the font and
+                      display interrupt is used without it
+  --show-flags NAME   print the saved SUPER-CHIP RPL flags of a ROM by file name, or all, and exit
+  --clear-flags NAME  delete the saved RPL flags of a ROM by file name, or all, and exit;
+                      they are kept in $XDG_DATA_HOME/ivsemu/flags (~/.local/share/ivsemu/flags)
+  --font NAME         hex digit font: vip, dream6800, eti660, schip or octo (default: the platform's)
+  --font-address ADDR hex address of the small font, the big font follows it (default 050, 080 on dream6800)
+  --screenshot-dir DIR directory for F12 screenshots (default .)
+  --record FILE       record from the first frame to FILE, an animated .gif or a .y4m with a .wav
+  --record-format FMT gif or y4m, for recordings started with F9 (default gif)
+  --record-scale N    recording scale factor (default 4)
+  --key-X KEYS        host keys for CHIP-8 key X (0-F) by physical position, e.g. --key-5 W,Up
+  --hotkey-ACTION KEYS host keys for an emulator command, or none, e.g. --hotkey-reset F3;
+                      keys bound to the CHIP-8 keypad are not hotkeys;
+                      actions: quit, reset, speed-up, speed-down, pause, menu, save-state,
+                      load-state, next-slot, screenshot, record, palette, persistence, filter,
+                      fullscreen, integer-scaling, keypad, status-line, open-rom,
+                      rebind-keys, mute
+  --pad-INPUT KEY     CHIP-8 key (0-F or none) for a controller button or stick direction,
+                      e.g. --pad-a 6, --pad-dpup 5, --pad-leftx- 7
+  --coverage PREFIX   write PREFIX.txt, PREFIX.png and PREFIX.pbm coverage maps on exit
+  --frequency HZ      beeper frequency (default 440)
+  --volume LEVEL      beeper volume between 0 and 1 (default 0.25)
+  --waveform NAME     square, triangle, sawtooth or sine (default square)
+  --vsync on|off      wait for the display refresh when presenting (default on)
+  --integer-scaling on|off  scale by whole multiples only, toggled with I (default on)
+  --fullscreen on|off start in fullscreen, toggled with F11 (default off)
+  --keypad on|off     show the clickable hex keypad, toggled with K (default off)
+  --status-line on|off show FPS, instructions per frame and the ROM title, toggled with F3 (default off)
+  --filter NAME       none, scanlines, grid, glow, crt, scale2x, scale3x or epx, cycled with G (default none)
+  --palette NAME      classic, lcd, amber or octo (default classic)
+  --colors HEX,HEX    custom palette of 2 to 4 colors: background, plane 1, plane 2, both planes
+  --persistence MODE  off, blend (OR of the last two frames) or phosphor (default off)
+  --phosphor-frames N frames a phosphor pixel takes to fade out, up to 16 (default 6)";
+
+pub struct Config {
+    pub rom: Option<String>,
+    pub rom_dir: String,
+    pub rom_database: Option<String>,
+    pub watch: bool,
+    pub patches: Vec<String>,
+    pub auto_patch: bool,
+    pub pause_on_load: bool,
+    pub headless: Option<u32>,
+    pub wav: Option<String>,
+    pub clock_hz: f64,
+    pub quirks: Quirks,
+    pub platform: Platform,
+    pub vip_interpreter: Option<String>,
+    pub vip_monitor: Option<String>,
+    pub show_flags: Option<String>,
+    pub clear_flags: Option<String>,
+    font: Option<SystemFont>, // The platform's when not set
+    font_address: Option<usize>,
+    machine_code: Option<bool>, // The platform's default when not set
+    pub coverage: Option<String>,
+    pub screenshot_dir: String,
+    pub record: Option<String>,
+    pub record_format: String,
+    pub record_scale: u32,
+    pub tone: Tone,
+    pub vsync: bool,
+    pub integer_scaling: bool,
+    pub fullscreen: bool,
+    pub keypad_overlay: bool,
+    pub status_line: bool,
+    pub filter: Filter,
+    pub key_bindings: Vec<(usize, Vec<Scancode>)>,
+    pub hotkeys: Hotkeys,
+    pub controller: ControllerMap,
+    pub palettes: Vec<Palette>,
+    pub palette: usize,
+    pub persistence: PersistenceMode,
+    pub phosphor_frames: u32,
+    config_file: Option<String>,
+    options: Vec<(String, String)>, // Command line options, kept to rebuild the config for another ROM
+    remembered: Vec<(String, String)>, // Options changed while running, replayed on a rebuild
+}
+
+impl Config {
+    pub fn new() -> Config {
+        Config {
+            rom: None,
+            rom_dir: ".".to_string(),
+            rom_database: None,
+            watch: true,
+            patches: vec![],
+            auto_patch: true,
+            pause_on_load: false,
+            headless: None,
+            wav: None,
+            clock_hz: 60.,
+            quirks: Quirks::new(),
+            platform: Platform::Chip8,
+            vip_interpreter: None,
+            vip_monitor: None,
+            show_flags: None,
+            clear_flags: None,
+            font: None,
+            font_address: None,
+            machine_code: None,
+            coverage: None,
+            screenshot_dir: ".".to_string(),
+            record: None,
+            record_format: "gif".to_string(),
+            record_scale: 4,
+            tone: Tone::new(),
+            vsync: true,
+            integer_scaling: true,
+            fullscreen: false,
+            keypad_overlay: false,
+            status_line: false,
+            filter: Filter::None,
+            key_bindings: vec![],
+            hotkeys: Hotkeys::new(),
+            controller: ControllerMap::new(),
+            palettes: Palette::builtin(),
+            palette: 0,
+            persistence: PersistenceMode::Off,
+            phosphor_frames: 6,
+            config_file: None,
+            options: vec![],
+            remembered: vec![],
+        }
+    }
+
+    pub fn from_args() -> Result<Config, String> {
+        let mut rom = None;
+        let mut options = vec![];
+        let mut config_file = None;
+        let mut args = std::env::args().skip(1);
+        while let Some(arg) = args.next() {
+            if let Some(option) = arg.strip_prefix("--") {
+                if option == "help" {
+                    return Err(USAGE.to_string());
+                }
+                let value = args
+                    .next()
+                    .ok_or(format!("missing value for --{}\n{}", option, USAGE))?;
+                if option == "config" {
+                    config_file = Some(value);
+                } else {
+                    options.push((option.to_string(), value));
+                }
+            } else {
+                rom = Some(arg);
+            }
+        }
+
+        Config::build(rom, config_file, options)
+    }
+
+    // Rebuilds the configuration for another ROM so that its profile section applies.
+    // Patches from the command line were meant for the ROM given there and are left out.
+    // Key bindings made while running carry over, other remembered options are per ROM.
+    pub fn with_rom(&self, rom: &str) -> Result<Config, String> {
+        let options = self.options.iter().filter(|(option, _)| option != "patch").cloned().collect();
+        let mut config = Config::build(Some(rom.to_string()), self.config_file.clone(), options)?;
+        for (option, value) in self.remembered.iter().filter(|(option, _)| option.starts_with("key-")) {
+            config.remember(option, value)?;
+        }
+        Ok(config)
+    }
+
+    // Rebuilds the configuration for the same ROM, e.g. when a cartridge's options changed on disk
+    pub fn reload(&self) -> Result<Config, String> {
+        let mut config = Config::build(self.rom.clone(), self.config_file.clone(), self.options.clone())?;
+        for (option, value) in self.remembered.iter() {
+            config.remember(option, value)?;
+        }
+        Ok(config)
+    }
+
+    // Sets an option changed while running so that it survives a rebuild
+    pub fn remember(&mut self, option: &str, value: &str) -> Result<(), String> {
+        self.set(option, value)?;
+        self.remembered.retain(|(o, _)| o != option);
+        self.remembered.push((option.to_string(), value.to_string()));
+        Ok(())
+    }
+
+    // Options from the config file are applied first so the command line overrides them
+    fn build(
+        rom: Option<String>,
+        config_file: Option<String>,
+        options: Vec<(String, String)>,
+    ) -> Result<Config, String> {
+        // The ROM is known before reading the file so its profile section can be picked
+        let mut config = Config::new();
+        let rom_from_args = rom.is_some();
+        config.rom = rom;
+        let extension = config.rom.as_ref().and_then(|rom| std::path::Path::new(rom).extension());
+        if let Some(platform) = extension.and_then(|extension| Platform::from_extension(&extension.to_string_lossy())) {
+            config.platform = platform;
+        }
+        let is_cartridge = |rom: &String| octo::is_cartridge(rom) && loader::split_archive(rom).is_none();
+        if let Some(rom) = config.rom.clone().filter(is_cartridge) {
+            config.apply_cartridge_options(&rom)?;
+        }
+        match &config_file {
+            Some(filename) => config.load_file(filename, rom_from_args)?,
+            None if std::path::Path::new(DEFAULT_CONFIG).exists() => {
+                config.load_file(DEFAULT_CONFIG, rom_from_args)?
+            }
+            None => {}
+        }
+        for (option, value) in options.iter() {
+            config.set(option, value)?;
+        }
+        if config.machine_code == Some(true) && !config.platform.has_1802() {
+            return Err("the dream6800 has no 1802 to run machine code on".to_string());
+        }
+        if config.platform == Platform::Vip && config.vip_interpreter.is_none() {
+            return Err("the vip platform needs the interpreter image, give it with --vip-interpreter FILE".to_string());
+        }
+        // Checked once every option is known since the platform moves the program
+        let fonts_end = config.get_font_address().checked_add(fonts::SMALL_SIZE + fonts::BIG_SIZE);
+        if !matches!(fonts_end, Some(end) if end <= config.platform.rom_address()) {
+            return Err(format!(
+                "the fonts at {:03X} do not fit below the program at {:03X}",
+                config.get_font_address(),
+                config.platform.rom_address()
+            ));
+        }
+        config.config_file = config_file;
+        config.options = options;
+        Ok(config)
+    }
+
+    pub fn load_file(&mut self, filename: &str, rom_from_args: bool) -> Result<(), String> {
+        let contents = std::fs::read_to_string(filename)
+            .map_err(|e| format!("unable to read {}: {}", filename, e))?;
+        let mut in_other_profile = false;
+        for (number, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if let Some(section) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+                in_other_profile = section.trim() != self.get_rom_name();
+                continue;
+            }
+            if in_other_profile {
+                continue;
+            }
+            let (option, value) = line
+                .split_once('=')
+                .ok_or(format!("{}:{}: expected `option = value`", filename, number + 1))?;
+            match option.trim() {
+                "rom" if rom_from_args => {}
+                "rom" => self.rom = Some(value.trim().to_string()),
+                option => self
+                    .set(option, value.trim())
+                    .map_err(|e| format!("{}:{}: {}", filename, number + 1, e))?,
+            }
+        }
+        Ok(())
+    }
+
+    // The options embedded in an Octo cartridge come before the config file and the command line
+    fn apply_cartridge_options(&mut self, filename: &str) -> Result<(), String> {
+        let cartridge = octo::read_cartridge(filename)?;
+        if let Some(clock_hz) = octo::get_clock_hz(&cartridge.options) {
+            self.clock_hz = clock_hz;
+        }
+        if let Some(font) = octo::get_font(&cartridge.options) {
+            self.font = Some(font);
+        }
+        for (name, on) in octo::get_quirks(&cartridge.options) {
+            self.quirks.set(name, on);
+        }
+        if let Some(palette) = octo::get_palette(&cartridge.options) {
+            self.palettes.retain(|p| p.name != palette.name);
+            self.palettes.push(palette);
+            self.palette = self.palettes.len() - 1;
+        }
+        Ok(())
+    }
+
+    // File name of the ROM, which names its profile section, inside its archive if it has one
+    pub fn get_rom_name(&self) -> String {
+        self.rom
+            .as_ref()
+            .map(|rom| loader::split_archive(rom).map_or(rom.as_str(), |(_, entry)| entry))
+            .and_then(|rom| std::path::Path::new(rom).file_name())
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default()
+    }
+
+    pub fn get_font(&self) -> SystemFont {
+        self.font.unwrap_or_else(|| self.platform.font())
+    }
+
+    pub fn get_font_address(&self) -> usize {
+        self.font_address.unwrap_or_else(|| self.platform.font_address())
+    }
+
+    pub fn get_machine_code(&self) -> bool {
+        self.machine_code.unwrap_or_else(|| self.platform.runs_machine_code())
+    }
+
+    pub fn get_palette(&self) -> &Palette {
+        &self.palettes[self.palette]
+    }
+
+    pub fn next_palette(&mut self) -> &Palette {
+        self.palette = (self.palette + 1) % self.palettes.len();
+        self.get_palette()
+    }
+
+    pub fn set(&mut self, option: &str, value: &str) -> Result<(), String> {
+        match option {
+            "headless" => {
+                self.headless = Some(
+                    value
+                        .parse()
+                        .map_err(|_| format!("invalid frame count: {}", value))?,
+                )
+            }
+            "wav" => self.wav = Some(value.to_string()),
+            "rom-dir" => self.rom_dir = value.to_string(),
+            "rom-database" => self.rom_database = Some(value.to_string()),
+            "watch" => self.watch = parse_bool(value)?,
+            "patch" => self.patches.push(value.to_string()),
+            "auto-patch" => self.auto_patch = parse_bool(value)?,
+            "machine-code" => self.machine_code = Some(parse_bool(value)?),
+            "pause-on-load" => self.pause_on_load = parse_bool(value)?,
+            "clock" => {
+                self.clock_hz = value
+                    .parse()
+                    .map_err(|_| format!("invalid clock: {}", value))?
+            }
+            "platform" => {
+                self.platform = Platform::from_name(value).ok_or(format!("unknown platform: {}", value))?
+            }
+            "vip-interpreter" => self.vip_interpreter = Some(value.to_string()),
+            "vip-monitor" => self.vip_monitor = Some(value.to_string()),
+            "show-flags" => self.show_flags = Some(value.to_string()),
+            "clear-flags" => self.clear_flags = Some(value.to_string()),
+            "font" => {
+                self.font = Some(SystemFont::from_name(value).ok_or(format!("unknown font: {}", value))?)
+            }
+            "font-address" => {
+                let digits = value.trim_start_matches("0x");
+                self.font_address = Some(
+                    usize::from_str_radix(digits, 16).map_err(|_| format!("invalid font address: {}", value))?,
+                )
+            }
+            "coverage" => self.coverage = Some(value.to_string()),
+            "screenshot-dir" => self.screenshot_dir = value.to_string(),
+            "record" => self.record = Some(value.to_string()),
+            "record-format" => match value {
+                "gif" | "y4m" => self.record_format = value.to_string(),
+                _ => return Err(format!("unknown recording format: {}", value)),
+            },
+            "record-scale" => {
+                self.record_scale = value
+                    .parse()
+                    .map_err(|_| format!("invalid scale: {}", value))?
+            }
+            "vsync" => self.vsync = parse_bool(value)?,
+            "integer-scaling" => self.integer_scaling = parse_bool(value)?,
+            "fullscreen" => self.fullscreen = parse_bool(value)?,
+            "keypad" => self.keypad_overlay = parse_bool(value)?,
+            "status-line" => self.status_line = parse_bool(value)?,
+            "filter" => {
+                self.filter =
+                    Filter::from_name(value).ok_or(format!("unknown filter: {}", value))?
+            }
+            "palette" => {
+                self.palette = self
+                    .palettes
+                    .iter()
+                    .position(|palette| palette.name == value)
+                    .ok_or(format!("unknown palette: {}", value))?
+            }
+            "colors" => {
+                let custom = Palette::from_hex_list("custom", value)?;
+                self.palettes.retain(|palette| palette.name != "custom");
+                self.palettes.push(custom);
+                self.palette = self.palettes.len() - 1;
+            }
+            "frequency" => {
+                self.tone.frequency = value
+                    .parse()
+                    .map_err(|_| format!("invalid frequency: {}", value))?
+            }
+            "volume" => {
+                let volume: f32 = value
+                    .parse()
+                    .map_err(|_| format!("invalid volume: {}", value))?;
+                self.tone.volume = volume.clamp(0., 1.);
+            }
+            "waveform" => {
+                self.tone.waveform = Waveform::from_name(value)
+                    .ok_or(format!("unknown waveform: {}", value))?
+            }
+            "persistence" => {
+                self.persistence = PersistenceMode::from_name(value, self.phosphor_frames)
+                    .ok_or(format!("unknown persistence mode: {}", value))?
+            }
+            "phosphor-frames" => {
+                self.phosphor_frames = value
+                    .parse()
+                    .map_err(|_| format!("invalid frame count: {}", value))?;
+                if let PersistenceMode::Phosphor(_) = self.persistence {
+                    self.persistence = PersistenceMode::Phosphor(self.phosphor_frames);
+                }
+            }
+            _ if option.starts_with("quirk-") => {
+                let name = &option[6..];
+                if !self.quirks.set(name, parse_bool(value)?) {
+                    return Err(format!("unknown quirk {}, expected one of {}", name, QUIRK_NAMES.join(", ")));
+                }
+            }
+            _ if option.starts_with("pad-") => {
+                let key = match value {
+                    "none" => None,
+                    _ => Some(
+                        usize::from_str_radix(value, 16)
+                            .ok()
+                            .filter(|&key| key < 0x10)
+                            .ok_or(format!("unknown CHIP-8 key: {}", value))?,
+                    ),
+                };
+                self.controller.bind(&option[4..], key)?;
+            }
+            _ if option.starts_with("key-") => {
+                let key = usize::from_str_radix(&option[4..], 16)
+                    .ok()
+                    .filter(|&key| key < 0x10)
+                    .ok_or(format!("unknown CHIP-8 key: {}", &option[4..]))?;
+                let scancodes = value
+                    .split(',')
+                    .map(|name| {
+                        Scancode::from_name(name.trim()).ok_or(format!("unknown key: {}", name))
+                    })
+                    .collect::<Result<Vec<Scancode>, String>>()?;
+                self.key_bindings.retain(|(k, _)| *k != key);
+                self.key_bindings.push((key, scancodes));
+            }
+            _ if option.starts_with("hotkey-") => {
+                let action = Action::from_name(&option[7..])
+                    .ok_or(format!("unknown action: {}", &option[7..]))?;
+                let keycodes = match value {
+                    "none" => vec![],
+                    _ => value
+                        .split(',')
+                        .map(|name| {
+                            Keycode::from_name(name.trim()).ok_or(format!("unknown key: {}", name))
+                        })
+                        .collect::<Result<Vec<Keycode>, String>>()?,
+                };
+                self.hotkeys.bind(action, &keycodes);
+            }
+            _ => return Err(format!("unknown option --{}\n{}", option, USAGE)),
+        }
+        Ok(())
+    }
+}
+
+fn parse_bool(value: &str) -> Result<bool, String> {
+    match value {
+        "on" | "true" | "yes" | "1" => Ok(true),
+        "off" | "false" | "no" | "0" => Ok(false),
+        _ => Err(format!("expected on or off, got {}", value)),
+    }
+}
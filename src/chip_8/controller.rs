@@ -0,0 +1,145 @@
+use super::cpu::cpu::CPU;
+
+use sdl2::controller::{Axis, Button, GameController};
+use sdl2::event::Event;
+use sdl2::GameControllerSubsystem;
+use std::collections::HashMap;
+
+const AXIS_THRESHOLD: i16 = 16000;
+
+// Gamepad buttons and stick directions to CHIP-8 keys
+#[derive(Clone)]
+pub struct ControllerMap {
+    buttons: HashMap<Button, usize>,
+    axes: HashMap<(Axis, bool), usize>, // (axis, positive direction)
+}
+
+impl ControllerMap {
+    // The D-pad and left stick cover the common 5/7/8/9 movement keys
+    pub fn new() -> ControllerMap {
+        ControllerMap {
+            buttons: [
+                (Button::DPadUp, 0x5),
+                (Button::DPadLeft, 0x7),
+                (Button::DPadDown, 0x8),
+                (Button::DPadRight, 0x9),
+                (Button::A, 0x6),
+                (Button::B, 0x4),
+                (Button::X, 0x1),
+                (Button::Y, 0x2),
+                (Button::Back, 0x0),
+                (Button::Start, 0xF),
+            ]
+            .iter()
+            .cloned()
+            .collect(),
+            axes: [
+                ((Axis::LeftY, false), 0x5),
+                ((Axis::LeftX, false), 0x7),
+                ((Axis::LeftY, true), 0x8),
+                ((Axis::LeftX, true), 0x9),
+            ]
+            .iter()
+            .cloned()
+            .collect(),
+        }
+    }
+
+    // `input` is an SDL button name such as "a" or "dpup", or an axis direction such as "leftx-"
+    pub fn bind(&mut self, input: &str, key: Option<usize>) -> Result<(), String> {
+        if let Some(axis) = input
+            .strip_suffix('+')
+            .or_else(|| input.strip_suffix('-'))
+            .and_then(Axis::from_string)
+        {
+            let direction = (axis, input.ends_with('+'));
+            match key {
+                Some(key) => self.axes.insert(direction, key),
+                None => self.axes.remove(&direction),
+            };
+        } else {
+            let button =
+                Button::from_string(input).ok_or(format!("unknown controller input: {}", input))?;
+            match key {
+                Some(key) => self.buttons.insert(button, key),
+                None => self.buttons.remove(&button),
+            };
+        }
+        Ok(())
+    }
+}
+
+pub struct Controllers {
+    subsystem: GameControllerSubsystem,
+    open: Vec<GameController>,
+    map: ControllerMap,
+    pressed_axes: HashMap<(u32, Axis), usize>, // Key held by a stick, per controller
+}
+
+impl Controllers {
+    pub fn init(sdl_context: &sdl2::Sdl, map: ControllerMap) -> Result<Controllers, String> {
+        Ok(Controllers {
+            subsystem: sdl_context.game_controller()?,
+            open: vec![],
+            map,
+            pressed_axes: HashMap::new(),
+        })
+    }
+
+    pub fn set_map(&mut self, map: ControllerMap) {
+        self.map = map;
+    }
+
+    // Returns true when the event was a controller event
+    pub fn handle_event(&mut self, event: &Event, cpu: &mut CPU) -> bool {
+        match *event {
+            Event::ControllerDeviceAdded { which, .. } => {
+                match self.subsystem.open(which) {
+                    Ok(controller) => {
+                        println!("Controller connected: {}", controller.name());
+                        self.open.push(controller);
+                    }
+                    Err(e) => println!("Unable to open controller {}: {}", which, e),
+                }
+            }
+            Event::ControllerDeviceRemoved { which, .. } => {
+                self.open.retain(|controller| controller.instance_id() != which);
+            }
+            Event::ControllerButtonDown { button, .. } => {
+                if let Some(&key) = self.map.buttons.get(&button) {
+                    cpu.press_key(key);
+                }
+            }
+            Event::ControllerButtonUp { button, .. } => {
+                if let Some(&key) = self.map.buttons.get(&button) {
+                    cpu.release_key(key);
+                }
+            }
+            Event::ControllerAxisMotion {
+                which, axis, value, ..
+            } => {
+                let direction = if value >= AXIS_THRESHOLD {
+                    Some(true)
+                } else if value <= -AXIS_THRESHOLD {
+                    Some(false)
+                } else {
+                    None
+                };
+                let key = direction.and_then(|positive| self.map.axes.get(&(axis, positive)).copied());
+                let previous = self.pressed_axes.get(&(which, axis)).copied();
+                if key != previous {
+                    if let Some(previous) = previous {
+                        cpu.release_key(previous);
+                        self.pressed_axes.remove(&(which, axis));
+                    }
+                    if let Some(key) = key {
+                        cpu.press_key(key);
+                        self.pressed_axes.insert((which, axis), key);
+                    }
+                }
+            }
+            _ => return false,
+        }
+        true
+    }
+}
@@ -1,8 +1,14 @@
 pub mod cpu;
 
+mod cdp1802;
 mod clock;
+mod coverage;
+pub mod fonts;
 mod frame_buffer;
 mod keypad;
 mod opcodes;
+pub mod platform;
+pub mod quirks;
 mod ram;
 mod registers;
+mod vip;
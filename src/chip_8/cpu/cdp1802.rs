@@ -0,0 +1,389 @@
+// RCA CDP1802, the CPU of the COSMAC VIP and the ETI-660. Memory and I/O go through a Bus.
+pub trait Bus {
+    fn read(&mut self, address: u16) -> u8;
+    fn write(&mut self, address: u16, value: u8);
+    // OUT 1-7 and INP 1-7
+    fn output(&mut self, _port: u8, _value: u8) {}
+    fn input(&mut self, _port: u8) -> u8 {
+        0
+    }
+    // External flags EF1-EF4, numbered from 0
+    fn flag(&mut self, _flag: usize) -> bool {
+        false
+    }
+}
+
+#[derive(Clone)]
+pub struct Cdp1802 {
+    pub r: [u16; 16], // Scratchpad registers
+    pub d: u8,        // Accumulator
+    pub df: bool,     // Carry
+    pub p: usize,     // Index of the program counter
+    pub x: usize,     // Index of the data pointer
+    pub t: u8,        // X and P saved by an interrupt or MARK
+    pub ie: bool,     // Interrupt enable
+    pub q: bool,      // Output flip-flop
+    pub idle: bool,   // Waiting in IDL for an interrupt or DMA
+}
+
+impl Cdp1802 {
+    pub fn new() -> Cdp1802 {
+        // Reset state: everything cleared but R0 is the program counter and interrupts are enabled
+        Cdp1802 {
+            r: [0; 16],
+            d: 0,
+            df: false,
+            p: 0,
+            x: 0,
+            t: 0,
+            ie: true,
+            q: false,
+            idle: false,
+        }
+    }
+
+    // Runs one instruction and returns the machine cycles it took, 8 clocks each
+    pub fn step(&mut self, bus: &mut dyn Bus) -> u32 {
+        if self.idle {
+            return 1;
+        }
+        let opcode = self.fetch(bus);
+        let n = (opcode & 0xF) as usize;
+        match opcode >> 4 {
+            0x0 if n == 0 => self.idle = true,
+            0x0 => self.d = bus.read(self.r[n]),
+            0x1 => self.r[n] = self.r[n].wrapping_add(1),
+            0x2 => self.r[n] = self.r[n].wrapping_sub(1),
+            0x3 => {
+                let taken = self.condition(bus, n);
+                self.short_branch(bus, taken);
+            }
+            0x4 => {
+                self.d = bus.read(self.r[n]);
+                self.r[n] = self.r[n].wrapping_add(1);
+            }
+            0x5 => bus.write(self.r[n], self.d),
+            0x6 => self.input_output(bus, n),
+            0x7 => self.op_7n(bus, n),
+            0x8 => self.d = self.r[n] as u8,
+            0x9 => self.d = (self.r[n] >> 8) as u8,
+            0xA => self.r[n] = (self.r[n] & 0xFF00) | self.d as u16,
+            0xB => self.r[n] = (self.r[n] & 0x00FF) | (self.d as u16) << 8,
+            0xC => {
+                self.long_branch_or_skip(bus, n);
+                return 3;
+            }
+            0xD => self.p = n,
+            0xE => self.x = n,
+            _ => self.op_fn(bus, n),
+        }
+        2
+    }
+
+    // Saves X and P in T and jumps to the handler in R1, when interrupts are enabled.
+    // Returns whether it was taken, which costs a machine cycle.
+    pub fn interrupt(&mut self) -> bool {
+        if !self.ie {
+            return false;
+        }
+        self.t = (self.x << 4 | self.p) as u8;
+        self.p = 1;
+        self.x = 2;
+        self.ie = false;
+        self.idle = false;
+        true
+    }
+
+    // A DMA output cycle, reading the byte at R0 for the device
+    pub fn dma_out(&mut self, bus: &mut dyn Bus) -> u8 {
+        let value = bus.read(self.r[0]);
+        self.r[0] = self.r[0].wrapping_add(1);
+        self.idle = false;
+        value
+    }
+
+    fn fetch(&mut self, bus: &mut dyn Bus) -> u8 {
+        let value = bus.read(self.r[self.p]);
+        self.r[self.p] = self.r[self.p].wrapping_add(1);
+        value
+    }
+
+    fn read_x(&mut self, bus: &mut dyn Bus) -> u8 {
+        bus.read(self.r[self.x])
+    }
+
+    // Branch conditions 0-7, 8-F being their negations
+    fn condition(&mut self, bus: &mut dyn Bus, n: usize) -> bool {
+        let condition = match n & 7 {
+            0 => true,
+            1 => self.q,
+            2 => self.d == 0,
+            3 => self.df,
+            flag => bus.flag(flag - 4),
+        };
+        condition != (n >= 8)
+    }
+
+    // The target replaces the low byte of the program counter
+    fn short_branch(&mut self, bus: &mut dyn Bus, taken: bool) {
+        let pc = self.r[self.p];
+        if taken {
+            self.r[self.p] = (pc & 0xFF00) | bus.read(pc) as u16;
+        } else {
+            self.r[self.p] = pc.wrapping_add(1);
+        }
+    }
+
+    fn long_branch_or_skip(&mut self, bus: &mut dyn Bus, n: usize) {
+        let pc = self.r[self.p];
+        let (skip, taken) = match n {
+            0x4 => (true, false), // NOP
+            0x5 => (true, !self.q),
+            0x6 => (true, self.d != 0),
+            0x7 => (true, !self.df),
+            0x8 => (true, true),
+            0xC => (true, self.ie),
+            0xD => (true, self.q),
+            0xE => (true, self.d == 0),
+            0xF => (true, self.df),
+            n => (false, self.condition(bus, n)),
+        };
+        if skip {
+            if taken {
+                self.r[self.p] = pc.wrapping_add(2);
+            }
+        } else if taken {
+            self.r[self.p] = (bus.read(pc) as u16) << 8 | bus.read(pc.wrapping_add(1)) as u16;
+        } else {
+            self.r[self.p] = pc.wrapping_add(2);
+        }
+    }
+
+    // IRX, OUT 1-7 and INP 1-7, 68 being unused on the 1802
+    fn input_output(&mut self, bus: &mut dyn Bus, n: usize) {
+        let rx = self.r[self.x];
+        match n {
+            0 => self.r[self.x] = rx.wrapping_add(1),
+            1..=7 => {
+                let value = bus.read(rx);
+                bus.output(n as u8, value);
+                self.r[self.x] = rx.wrapping_add(1);
+            }
+            8 => {}
+            _ => {
+                self.d = bus.input(n as u8 - 8);
+                bus.write(rx, self.d);
+            }
+        }
+    }
+
+    fn op_7n(&mut self, bus: &mut dyn Bus, n: usize) {
+        match n {
+            0x0 | 0x1 => {
+                // RET and DIS
+                let value = self.read_x(bus);
+                self.r[self.x] = self.r[self.x].wrapping_add(1);
+                self.x = (value >> 4) as usize;
+                self.p = (value & 0xF) as usize;
+                self.ie = n == 0;
+            }
+            0x2 => {
+                self.d = self.read_x(bus);
+                self.r[self.x] = self.r[self.x].wrapping_add(1);
+            }
+            0x3 => {
+                bus.write(self.r[self.x], self.d);
+                self.r[self.x] = self.r[self.x].wrapping_sub(1);
+            }
+            0x4 => {
+                let value = self.read_x(bus);
+                self.add(value, self.df);
+            }
+            0x5 => {
+                let value = self.read_x(bus);
+                self.subtract(value, self.d, self.df);
+            }
+            0x6 => {
+                let carry = self.d & 1 != 0;
+                self.d = self.d >> 1 | (self.df as u8) << 7;
+                self.df = carry;
+            }
+            0x7 => {
+                let value = self.read_x(bus);
+                self.subtract(self.d, value, self.df);
+            }
+            0x8 => bus.write(self.r[self.x], self.t),
+            0x9 => {
+                // MARK
+                self.t = (self.x << 4 | self.p) as u8;
+                bus.write(self.r[2], self.t);
+                self.x = self.p;
+                self.r[2] = self.r[2].wrapping_sub(1);
+            }
+            0xA => self.q = false,
+            0xB => self.q = true,
+            0xC => {
+                let value = self.fetch(bus);
+                self.add(value, self.df);
+            }
+            0xD => {
+                let value = self.fetch(bus);
+                self.subtract(value, self.d, self.df);
+            }
+            0xE => {
+                let carry = self.d & 0x80 != 0;
+                self.d = self.d << 1 | self.df as u8;
+                self.df = carry;
+            }
+            _ => {
+                let value = self.fetch(bus);
+                self.subtract(self.d, value, self.df);
+            }
+        }
+    }
+
+    // Logic and arithmetic on M(R(X)), or on the immediate byte for F8-FF
+    fn op_fn(&mut self, bus: &mut dyn Bus, n: usize) {
+        let value = match n {
+            0x6 | 0xE => 0,
+            0x0..=0x7 => self.read_x(bus),
+            _ => self.fetch(bus),
+        };
+        match n & 7 {
+            0x0 => self.d = value,
+            0x1 => self.d |= value,
+            0x2 => self.d &= value,
+            0x3 => self.d ^= value,
+            0x4 => self.add(value, false),
+            0x5 => self.subtract(value, self.d, true),
+            0x6 if n == 0x6 => {
+                self.df = self.d & 1 != 0;
+                self.d >>= 1;
+            }
+            0x6 => {
+                self.df = self.d & 0x80 != 0;
+                self.d <<= 1;
+            }
+            _ => self.subtract(self.d, value, true),
+        }
+    }
+
+    fn add(&mut self, value: u8, carry: bool) {
+        let sum = self.d as u16 + value as u16 + carry as u16;
+        self.d = sum as u8;
+        self.df = sum > 0xFF;
+    }
+
+    // DF is set when there is no borrow
+    fn subtract(&mut self, minuend: u8, subtrahend: u8, no_borrow: bool) {
+        let difference = minuend as i16 - subtrahend as i16 - !no_borrow as i16;
+        self.d = difference as u8;
+        self.df = difference >= 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Memory([u8; 0x100]);
+
+    impl Bus for Memory {
+        fn read(&mut self, address: u16) -> u8 {
+            self.0[address as usize & 0xFF]
+        }
+
+        fn write(&mut self, address: u16, value: u8) {
+            self.0[address as usize & 0xFF] = value;
+        }
+    }
+
+    // The program at 00 with R0 as the program counter, and the operand M(R1) at 80
+    fn run(program: &[u8], steps: usize, cpu: &mut Cdp1802, operand: u8) -> Memory {
+        let mut memory = Memory([0; 0x100]);
+        memory.0[..program.len()].copy_from_slice(program);
+        memory.0[0x80] = operand;
+        cpu.r[1] = 0x80;
+        cpu.x = 1;
+        for _ in 0..steps {
+            cpu.step(&mut memory);
+        }
+        memory
+    }
+
+    fn subtract(opcode: u8, d: u8, df: bool, operand: u8) -> (u8, bool) {
+        let mut cpu = Cdp1802::new();
+        cpu.d = d;
+        cpu.df = df;
+        run(&[opcode, operand], 1, &mut cpu, operand);
+        (cpu.d, cpu.df)
+    }
+
+    #[test]
+    fn subtraction_sets_df_without_borrow() {
+        assert_eq!(subtract(0xF5, 0x10, false, 0x20), (0x10, true)); // SD: M - D
+        assert_eq!(subtract(0xF5, 0x20, true, 0x10), (0xF0, false));
+        assert_eq!(subtract(0xF7, 0x10, true, 0x20), (0xF0, false)); // SM: D - M
+        assert_eq!(subtract(0xF7, 0x20, false, 0x20), (0x00, true));
+        assert_eq!(subtract(0xFF, 0x10, false, 0x10), (0x00, true)); // SMI
+        assert_eq!(subtract(0x75, 0x10, false, 0x20), (0x0F, true)); // SDB: M - D - borrow
+        assert_eq!(subtract(0x75, 0x10, true, 0x20), (0x10, true));
+        assert_eq!(subtract(0x77, 0x10, false, 0x20), (0xEF, false)); // SMB: D - M - borrow
+        assert_eq!(subtract(0x77, 0x10, true, 0x20), (0xF0, false));
+        assert_eq!(subtract(0x77, 0x20, false, 0x20), (0xFF, false));
+        assert_eq!(subtract(0x77, 0x21, false, 0x20), (0x00, true));
+    }
+
+    #[test]
+    fn long_skips() {
+        type Setup = fn(&mut Cdp1802);
+        let skips: [(u8, Setup, bool); 12] = [
+            (0xC4, |_| {}, false), // NOP
+            (0xC5, |cpu| cpu.q = false, true),
+            (0xC5, |cpu| cpu.q = true, false),
+            (0xC6, |cpu| cpu.d = 1, true),
+            (0xC7, |cpu| cpu.df = false, true),
+            (0xC7, |cpu| cpu.df = true, false),
+            (0xC8, |_| {}, true),
+            (0xCC, |cpu| cpu.ie = true, true),
+            (0xCD, |cpu| cpu.q = true, true),
+            (0xCE, |cpu| cpu.d = 1, false),
+            (0xCE, |cpu| cpu.d = 0, true),
+            (0xCF, |cpu| cpu.df = true, true),
+        ];
+        for (opcode, setup, skipped) in skips.iter() {
+            let mut cpu = Cdp1802::new();
+            setup(&mut cpu);
+            let cycles = cpu.step(&mut Memory([*opcode; 0x100]));
+            assert_eq!(cpu.r[0], if *skipped { 3 } else { 1 }, "{:02X}", opcode);
+            assert_eq!(cycles, 3);
+        }
+
+        let mut cpu = Cdp1802::new();
+        cpu.df = true;
+        run(&[0xC3, 0x12, 0x34], 1, &mut cpu, 0); // LBDF
+        assert_eq!(cpu.r[0], 0x1234);
+    }
+
+    #[test]
+    fn mark_sav_ret() {
+        let mut cpu = Cdp1802::new();
+        cpu.r[2] = 0x90;
+        cpu.p = 3;
+        cpu.x = 5;
+        cpu.ie = false;
+        // MARK, SEX 2, SAV, INC 2, RET
+        let mut memory = Memory([0; 0x100]);
+        memory.0[..5].copy_from_slice(&[0x79, 0xE2, 0x78, 0x12, 0x70]);
+
+        cpu.step(&mut memory);
+        assert_eq!((cpu.t, memory.0[0x90], cpu.x, cpu.r[2]), (0x53, 0x53, 3, 0x8F));
+        cpu.t = 0x21;
+        cpu.step(&mut memory);
+        cpu.step(&mut memory);
+        assert_eq!(memory.0[0x8F], 0x21);
+        cpu.step(&mut memory);
+        cpu.step(&mut memory);
+        assert_eq!((cpu.x, cpu.p, cpu.r[2], cpu.ie), (5, 3, 0x91, true));
+    }
+}
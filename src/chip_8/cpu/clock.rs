@@ -1,61 +1,36 @@
-use std::time::SystemTime;
-
 pub struct Clock {
     pub tick: u8,
     clock_hz: f64,
-    elapsed: SystemTime,
 }
 
 impl Clock {
     pub fn new() -> Clock {
         Clock {
-            tick: 0xFF,
+            tick: 0x00,
             clock_hz: 60.,
-            elapsed: SystemTime::now(),
         }
     }
 
-    pub fn tick(&mut self) -> bool {
-        if let Ok(elapsed) = self.elapsed.elapsed() {
-            if elapsed.as_secs_f64() >= self.clock_hz_as_secs_f64() {
-                if self.tick > 0 {
-                    self.tick -= 1;
-                }
-                self.reset_elapsed();
-                return true;
-            }
+    pub fn step(&mut self) {
+        if self.tick > 0 {
+            self.tick -= 1;
         }
-        false
     }
 
-    fn reset_elapsed(&mut self) {
-        self.elapsed = SystemTime::now();
+    pub fn get_clock_hz(&self) -> f64 {
+        self.clock_hz
     }
 
-    pub fn clock_hz_as_secs_f64(&self) -> f64 {
-        1. / self.clock_hz
+    pub fn set_clock_hz(&mut self, clock_hz: f64) {
+        self.clock_hz = clock_hz.max(10.);
     }
 
-    pub fn increase_clock(&mut self, is_printing: bool) {
-        if is_printing {
-            println!(
-                "Increasing cpu clock from {:5} Hz to {:5} Hz",
-                self.clock_hz,
-                self.clock_hz + 10.
-            );
-        }
+    pub fn increase_clock(&mut self) {
         self.clock_hz += 10.;
     }
 
-    pub fn decrease_clock(&mut self, is_printing: bool) {
+    pub fn decrease_clock(&mut self) {
         if self.clock_hz > 10. {
-            if is_printing {
-                println!(
-                    "Decreasing cpu clock from {:5} Hz to {:5} Hz",
-                    self.clock_hz,
-                    self.clock_hz - 10.
-                );
-            }
             self.clock_hz -= 10.;
         }
     }
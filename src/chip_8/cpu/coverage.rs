@@ -0,0 +1,100 @@
+use crate::chip_8::image;
+
+use std::fs::File;
+use std::io::{BufWriter, Result, Write};
+
+pub const EXECUTED: u8 = 0b0001; // Fetched as an instruction
+pub const SPRITE: u8 = 0b0010; // Read by DXYN as sprite data
+pub const DATA_READ: u8 = 0b0100; // Read by FX65
+pub const DATA_WRITE: u8 = 0b1000; // Written by FX33/FX55
+
+const BYTES_PER_ROW: usize = 64;
+
+pub struct Coverage {
+    marks: Vec<u8>,
+    hits: Vec<u32>,
+}
+
+impl Coverage {
+    pub fn new(size: usize) -> Coverage {
+        Coverage {
+            marks: vec![0; size],
+            hits: vec![0; size],
+        }
+    }
+
+    pub fn mark(&mut self, addr: usize, kind: u8) {
+        if addr < self.marks.len() {
+            self.marks[addr] |= kind;
+            self.hits[addr] = self.hits[addr].saturating_add(1);
+        }
+    }
+
+    fn symbol(marks: u8) -> char {
+        match marks {
+            0 => '.',
+            EXECUTED => 'X',
+            SPRITE => 'S',
+            DATA_READ => 'R',
+            DATA_WRITE => 'W',
+            m if m == DATA_READ | DATA_WRITE => 'D',
+            _ => '*',
+        }
+    }
+
+    fn color(&self, addr: usize) -> [u8; 4] {
+        let base: [u8; 3] = match self.marks[addr] {
+            0 => return [0, 0, 0, 255],
+            EXECUTED => [255, 64, 64],
+            SPRITE => [64, 255, 64],
+            DATA_READ => [64, 128, 255],
+            DATA_WRITE => [255, 200, 0],
+            m if m == DATA_READ | DATA_WRITE => [0, 220, 255],
+            _ => [255, 255, 255],
+        };
+        // Brightness grows with the log of the access count, starting at 1/4
+        let intensity = (((self.hits[addr] as f64).log2() + 1.) / 16.).min(1.) * 0.75 + 0.25;
+        [
+            (base[0] as f64 * intensity) as u8,
+            (base[1] as f64 * intensity) as u8,
+            (base[2] as f64 * intensity) as u8,
+            255,
+        ]
+    }
+
+    pub fn export_text(&self, filename: &str) -> Result<()> {
+        let mut f = BufWriter::new(File::create(filename)?);
+        writeln!(f, "# ivsemu coverage map, {} bytes per row", BYTES_PER_ROW)?;
+        writeln!(
+            f,
+            "# X executed, S sprite data (DXYN), R read (FX65), W written (FX33/FX55), D read and written, * several, . never touched"
+        )?;
+        for kind in [EXECUTED, SPRITE, DATA_READ, DATA_WRITE].iter() {
+            let count = self.marks.iter().filter(|&&m| m & kind != 0).count();
+            writeln!(f, "# {}: {} bytes", Coverage::symbol(*kind), count)?;
+        }
+        for (row, marks) in self.marks.chunks(BYTES_PER_ROW).enumerate() {
+            let line: String = marks.iter().map(|&m| Coverage::symbol(m)).collect();
+            writeln!(f, "{:04X}  {}", row * BYTES_PER_ROW, line)?;
+        }
+        f.flush()
+    }
+
+    pub fn export_png(&self, filename: &str) -> Result<()> {
+        let height = self.marks.len().div_ceil(BYTES_PER_ROW);
+        let mut rgba = vec![0; BYTES_PER_ROW * height * 4];
+        for addr in 0..self.marks.len() {
+            rgba[addr * 4..addr * 4 + 4].copy_from_slice(&self.color(addr));
+        }
+        image::write_png(filename, BYTES_PER_ROW, height, &rgba)
+    }
+
+    pub fn export_pbm(&self, filename: &str) -> Result<()> {
+        let height = self.marks.len().div_ceil(BYTES_PER_ROW);
+        let mut pixels = vec![false; BYTES_PER_ROW * height];
+        for (addr, &m) in self.marks.iter().enumerate() {
+            pixels[addr] = m != 0;
+        }
+        image::write_pbm(filename, BYTES_PER_ROW, height, &pixels)
+    }
+}
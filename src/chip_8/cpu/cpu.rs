@@ -1,52 +1,145 @@
+use super::cdp1802::Cdp1802;
 use super::clock::Clock;
-use super::frame_buffer::{FrameBuffer, HEIGHT, WIDTH, PITCH_BYTES};
+use super::coverage::{self, Coverage};
+use super::fonts::SystemFont;
+use super::frame_buffer::{ColorZones, FrameBuffer, HEIGHT, WIDTH, ZONE_WIDTH};
 use super::keypad::Keypad;
 use super::opcodes::OpCodes;
+use super::platform::Platform;
+use super::quirks::Quirks;
 use super::ram::RAM;
 use super::registers::Registers;
+use super::vip::Vip;
+use crate::chip_8::display::{PersistenceMode, Region};
+use crate::chip_8::palette::Palette;
+use crate::chip_8::rpl::RplFlags;
 
-use sdl2::keyboard::Keycode;
+use sdl2::keyboard::Scancode;
 
 use rand::Rng;
 
+pub const FRAMES_PER_SECOND: u32 = 60;
+
+const MAX_ROUTINE_STEPS: u32 = 1_000_000; // 1802 instructions before a 0NNN routine is given up on
+
+// Snapshot of the emulated machine, leaving out host settings such as the clock, key bindings and palette
+#[derive(Clone)]
+pub struct State {
+    stack: Vec<usize>,
+    dt: u8,
+    st: u8,
+    cycle_debt: f64,
+    tone: bool,
+    regs: Registers,
+    ram: RAM,
+    rows: [u64; HEIGHT],
+    color_zones: Option<ColorZones>,
+    cosmac: Cdp1802,
+    vip: Option<Vip>,
+}
+
 pub struct CPU {
+    platform: Platform,
     stack: Vec<usize>,         // Function Stack
     dt: Clock,                 // Delay Timer
     st: Clock,                 // Sound Timer
     clock: Clock,              // CPU Clock
+    cycle_debt: f64,           // Fraction of an instruction carried to the next frame
+    tone: bool,                // Whether the last frame sounded the beeper
     regs: Registers,           // Registers
     ram: RAM,                  // RAM
     keypad: Keypad,            // Keypad
     frame_buffer: FrameBuffer, // Frame Buffer
     op: OpCodes,               // Operation Code,
-    pub should_redraw: bool,   // Boolean indicating Display Buffer update
+    coverage: Coverage,        // RAM coverage map
+    cosmac: Cdp1802,           // Runs 0NNN machine code routines
+    vip: Option<Vip>,          // Emulates the whole COSMAC VIP instead of interpreting
+    rpl_flags: RplFlags,       // FX75/FX85 storage, outlives resets and save states
+    quirks: Quirks,            // Behaviour of the instructions interpreters disagree on
+    drew: bool,                // A sprite was drawn this frame, which ends it with the vblank quirk
+    machine_code: bool,        // 0NNN calls 1802 routines
 }
 
 impl CPU {
-    pub fn new() -> CPU {
-        let mut ram = RAM::new();
-        ram.init_fonts();
+    pub fn new(platform: Platform) -> CPU {
+        let ram = RAM::new(platform);
+        let coverage = Coverage::new(ram.len());
+        let mut frame_buffer = FrameBuffer::new();
+        if platform == Platform::Chip8x {
+            frame_buffer.set_color_zones(Some(ColorZones::new()));
+        }
 
         CPU {
+            platform,
             stack: vec![],
             dt: Clock::new(),
             st: Clock::new(),
             clock: Clock::new(),
-            regs: Registers::new(),
+            cycle_debt: 0.,
+            tone: false,
+            regs: Registers::new(ram.get_rom_address()),
             ram: ram,
             keypad: Keypad::new(),
-            frame_buffer: FrameBuffer::new(),
+            frame_buffer,
             op: OpCodes::new(0000),
-            should_redraw: false,
+            coverage,
+            cosmac: Cdp1802::new(),
+            vip: None,
+            rpl_flags: RplFlags::new(),
+            quirks: Quirks::new(),
+            drew: false,
+            machine_code: platform.runs_machine_code(),
+        }
+    }
+
+    // Switches to emulating the VIP, with the interpreter image at 0000 running the ROM
+    pub fn boot_vip(&mut self, interpreter: &[u8], monitor: Option<&[u8]>) {
+        for (address, &byte) in interpreter.iter().enumerate() {
+            self.ram.write8(address, byte);
         }
+        self.vip = Some(Vip::new(monitor, self.ram.len()));
+    }
+
+    pub fn run_frame(&mut self) {
+        if let Some(vip) = &mut self.vip {
+            vip.run_frame(&mut self.ram, self.keypad.key_status);
+            self.tone = vip.is_tone_on();
+            self.frame_buffer.update_rows(vip.get_rows());
+            self.frame_buffer.end_frame();
+            return;
+        }
+        self.cycle_debt += self.instructions_per_frame();
+        while self.cycle_debt >= 1. {
+            self.step();
+            self.cycle_debt -= 1.;
+            if self.drew && self.quirks.vblank {
+                self.cycle_debt = self.cycle_debt.fract();
+                break;
+            }
+        }
+        self.drew = false;
+        // Sampled before the timer steps so that a sound timer of N beeps for N frames
+        self.tone = self.st.tick > 0;
+        self.step_timers();
+        self.frame_buffer.end_frame();
+    }
+
+    pub fn step(&mut self) {
+        self.fetch();
+        self.decode();
     }
 
     pub fn fetch(&mut self) {
         self.op = OpCodes::new(self.ram.read16(self.regs.pc));
+        self.coverage.mark(self.regs.pc, coverage::EXECUTED);
+        self.coverage.mark(self.regs.pc + 1, coverage::EXECUTED);
         self.regs.increment_pc();
     }
 
     pub fn decode(&mut self) {
+        if self.platform == Platform::Chip8x && self.decode_chip8x() {
+            return;
+        }
         //TODO: function pointers
         if self.decode_match("00E0") {
             op_00e0(self);
@@ -94,7 +187,6 @@ impl CPU {
             op_cxnn(self);
         } else if self.decode_match("D???") {
             op_dxyn(self);
-            self.should_redraw = true;
         } else if self.decode_match("E?9E") {
             op_ex9e(self);
         } else if self.decode_match("E?A1") {
@@ -111,17 +203,40 @@ impl CPU {
             op_fx0a(self);
         } else if self.decode_match("F?29") {
             op_fx29(self);
+        } else if self.decode_match("F?30") {
+            op_fx30(self);
         } else if self.decode_match("F?33") {
             op_fx33(self);
         } else if self.decode_match("F?55") {
             op_fx55(self);
         } else if self.decode_match("F?65") {
             op_fx65(self);
+        } else if self.decode_match("F?75") {
+            op_fx75(self);
+        } else if self.decode_match("F?85") {
+            op_fx85(self);
+        } else if self.decode_match("0???") && self.machine_code && !(0x0C0..=0x0FF).contains(&self.op.nnn) {
+            // 00C0 to 00FF are left to SUPER-CHIP and XO-CHIP, never machine code
+            op_0nnn(self);
         } else {
             println! {"Unknown instruction: {:04x}", self.op.opcode};
         }
     }
 
+    // Instructions CHIP-8X adds or replaces, returns false for the others
+    fn decode_chip8x(&mut self) -> bool {
+        if self.decode_match("02A0") {
+            op_02a0(self);
+        } else if self.decode_match("5??1") {
+            op_5xy1(self);
+        } else if self.decode_match("B???") {
+            op_bxyn(self);
+        } else {
+            return false;
+        }
+        true
+    }
+
     fn decode_match(&self, hex_code: &str) -> bool {
         assert!(
             hex_code.len() == 4,
@@ -160,19 +275,42 @@ impl CPU {
     }
 
     pub fn reset_rom(&mut self) {
-        self.regs.reset_pc();
+        if let Some(vip) = &mut self.vip {
+            vip.reset(self.ram.len());
+        }
+        self.regs.reset_pc(self.ram.get_rom_address());
+    }
+
+    pub fn increase_clock(&mut self) {
+        self.clock.increase_clock();
+    }
+
+    pub fn decrease_clock(&mut self) {
+        self.clock.decrease_clock();
+    }
+
+    pub fn set_clock(&mut self, clock_hz: f64) {
+        self.clock.set_clock_hz(clock_hz);
+    }
+
+    pub fn get_clock_hz(&self) -> f64 {
+        self.clock.get_clock_hz()
     }
 
-    pub fn increase_clock(&mut self, is_printing: bool) {
-        self.clock.increase_clock(is_printing);
+    pub fn instructions_per_frame(&self) -> f64 {
+        self.clock.get_clock_hz() / FRAMES_PER_SECOND as f64
     }
 
-    pub fn decrease_clock(&mut self, is_printing: bool) {
-        self.clock.decrease_clock(is_printing);
+    pub fn compute_scancode(&mut self, scancode: Scancode) -> Option<usize> {
+        self.keypad.compute_scancode(scancode)
     }
 
-    pub fn compute_keycode(&mut self, keycode: Keycode) -> Option<usize> {
-        self.keypad.compute_keycode(keycode)
+    pub fn bind_key(&mut self, key_index: usize, scancodes: &[Scancode]) {
+        self.keypad.bind(key_index, scancodes);
+    }
+
+    pub fn get_key_bindings(&self, key_index: usize) -> Vec<Scancode> {
+        self.keypad.get_bindings(key_index)
     }
 
     pub fn press_key(&mut self, key_index: usize) {
@@ -183,6 +321,10 @@ impl CPU {
         self.keypad.release(key_index);
     }
 
+    pub fn get_key_status(&self) -> [bool; 0x10] {
+        self.keypad.key_status
+    }
+
     pub fn get_delay_timer(&self) -> u8 {
         self.dt.tick
     }
@@ -195,24 +337,101 @@ impl CPU {
         self.st.tick = tick;
     }
 
-    pub fn tick_delay_timer(&mut self) -> bool {
-        self.dt.tick()
+    pub fn is_sound_playing(&self) -> bool {
+        self.tone
+    }
+
+    pub fn step_timers(&mut self) {
+        self.dt.step();
+        self.st.step();
+    }
+
+    pub fn set_font(&mut self, font: SystemFont, address: usize) {
+        self.ram.init_fonts(font, address);
     }
 
-    pub fn tick_sound_timer(&mut self) -> bool {
-        self.st.tick()
+    pub fn set_rpl_flags(&mut self, rpl_flags: RplFlags) {
+        self.rpl_flags = rpl_flags;
     }
 
-    pub fn tick(&mut self) -> bool {
-        self.clock.tick()
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.quirks = quirks;
+    }
+
+    pub fn set_machine_code(&mut self, machine_code: bool) {
+        self.machine_code = machine_code && self.platform.has_1802();
     }
 
     pub fn load_rom(&mut self, rom: &[u8]) {
         self.ram.load_rom(rom);
     }
 
-    pub fn get_frame_buffer(&self) -> [u8; WIDTH * HEIGHT * PITCH_BYTES] {
-        self.frame_buffer.frame_buffer
+    pub fn max_rom_size(&self) -> usize {
+        self.ram.max_rom_size()
+    }
+
+    pub fn export_coverage(&self, prefix: &str) -> std::io::Result<()> {
+        self.coverage.export_text(&format!("{}.txt", prefix))?;
+        self.coverage.export_png(&format!("{}.png", prefix))?;
+        self.coverage.export_pbm(&format!("{}.pbm", prefix))
+    }
+
+    pub fn set_palette(&mut self, palette: &Palette) {
+        self.frame_buffer.set_palette(palette);
+    }
+
+    pub fn set_persistence(&mut self, persistence: PersistenceMode) {
+        self.frame_buffer.set_persistence(persistence);
+    }
+
+    // Row-major on/off state of every pixel
+    pub fn get_pixels(&self) -> Vec<bool> {
+        let mut pixels = Vec::with_capacity(WIDTH * HEIGHT);
+        for y in 0..HEIGHT {
+            for x in 0..WIDTH {
+                pixels.push(self.frame_buffer.get_pixel(x, y));
+            }
+        }
+        pixels
+    }
+
+    // Brings the RGBA frame buffer up to date, returning the regions that changed
+    pub fn render_frame_buffer(&mut self) -> Vec<Region> {
+        self.frame_buffer.render()
+    }
+
+    pub fn get_frame_buffer(&self) -> &[u8] {
+        self.frame_buffer.get_rgba()
+    }
+
+    pub fn save_state(&self) -> State {
+        State {
+            stack: self.stack.clone(),
+            dt: self.dt.tick,
+            st: self.st.tick,
+            cycle_debt: self.cycle_debt,
+            tone: self.tone,
+            regs: self.regs.clone(),
+            ram: self.ram.clone(),
+            rows: self.frame_buffer.get_rows(),
+            color_zones: self.frame_buffer.get_color_zones(),
+            cosmac: self.cosmac.clone(),
+            vip: self.vip.clone(),
+        }
+    }
+
+    pub fn load_state(&mut self, state: &State) {
+        self.stack = state.stack.clone();
+        self.dt.tick = state.dt;
+        self.st.tick = state.st;
+        self.cycle_debt = state.cycle_debt;
+        self.tone = state.tone;
+        self.regs = state.regs.clone();
+        self.ram = state.ram.clone();
+        self.frame_buffer.set_rows(state.rows);
+        self.frame_buffer.set_color_zones(state.color_zones);
+        self.cosmac = state.cosmac.clone();
+        self.vip = state.vip.clone();
     }
 }
 
@@ -220,6 +439,59 @@ fn op_00e0(cpu: &mut CPU) {
     cpu.frame_buffer.clear();
 }
 
+// Machine code routine, called the way the VIP interpreter does: V0-VF are at EF0 and the
+// display at F00 (for 4 KiB of RAM), I is in RA and the CHIP-8 program counter in R5, R2 is
+// the stack pointer and the routine runs with R3 as its program counter until SEP R4 (D4)
+fn op_0nnn(cpu: &mut CPU) {
+    let top = cpu.ram.len();
+    let (registers, display) = (top - 0x110, top - 0x100);
+    for v in 0..16 {
+        cpu.ram.write8(registers + v, cpu.regs.get(v));
+    }
+    let rows = cpu.frame_buffer.get_rows();
+    for (y, row) in rows.iter().enumerate() {
+        for (column, &byte) in row.to_be_bytes().iter().enumerate() {
+            cpu.ram.write8(display + y * 8 + column, byte);
+        }
+    }
+
+    let cosmac = &mut cpu.cosmac;
+    cosmac.r[0x2] = (top - 0x131) as u16;
+    cosmac.r[0x3] = cpu.op.nnn as u16;
+    cosmac.r[0x5] = cpu.regs.pc as u16;
+    cosmac.r[0xA] = cpu.regs.i as u16;
+    cosmac.r[0xB] = display as u16;
+    cosmac.p = 3;
+    cosmac.x = 2;
+    cosmac.idle = false;
+    let mut steps = 0;
+    while cosmac.p != 4 {
+        if cosmac.idle || steps == MAX_ROUTINE_STEPS {
+            println!("1802 routine at {:03x} did not return", cpu.op.nnn);
+            break;
+        }
+        cosmac.step(&mut cpu.ram);
+        steps += 1;
+    }
+
+    for v in 0..16 {
+        cpu.regs.set(v, cpu.ram.read8(registers + v));
+    }
+    cpu.regs.i = cpu.cosmac.r[0xA] as usize % top;
+    cpu.regs.pc = cpu.cosmac.r[0x5] as usize % top;
+    let mut new_rows = rows;
+    for (y, row) in new_rows.iter_mut().enumerate() {
+        let mut bytes = [0; 8];
+        for (column, byte) in bytes.iter_mut().enumerate() {
+            *byte = cpu.ram.read8(display + y * 8 + column);
+        }
+        *row = u64::from_be_bytes(bytes);
+    }
+    if new_rows != rows {
+        cpu.frame_buffer.set_rows(new_rows);
+    }
+}
+
 fn op_1nnn(cpu: &mut CPU) {
     cpu.regs.pc = cpu.op.nnn;
 }
@@ -257,6 +529,12 @@ fn op_5xy0(cpu: &mut CPU) {
     }
 }
 
+// CHIP-8X adds each nibble on its own, dropping the carry
+fn op_5xy1(cpu: &mut CPU) {
+    let (vx, vy) = (cpu.regs.get(cpu.op.x), cpu.regs.get(cpu.op.y));
+    cpu.regs.set(cpu.op.x, ((vx & 0x77) + (vy & 0x77)) & 0x77);
+}
+
 fn op_9xy0(cpu: &mut CPU) {
     if cpu.regs.get(cpu.op.x) != cpu.regs.get(cpu.op.y) {
         cpu.regs.increment_pc();
@@ -281,52 +559,71 @@ fn op_8xy1(cpu: &mut CPU) {
     let vx = cpu.regs.get(cpu.op.x);
     let vy = cpu.regs.get(cpu.op.y);
     cpu.regs.set(cpu.op.x, vx | vy);
+    if cpu.quirks.logic {
+        cpu.regs.set(0xF, 0);
+    }
 }
 
 fn op_8xy2(cpu: &mut CPU) {
     let vx = cpu.regs.get(cpu.op.x);
     let vy = cpu.regs.get(cpu.op.y);
     cpu.regs.set(cpu.op.x, vx & vy);
+    if cpu.quirks.logic {
+        cpu.regs.set(0xF, 0);
+    }
 }
 
 fn op_8xy3(cpu: &mut CPU) {
     let vx = cpu.regs.get(cpu.op.x);
     let vy = cpu.regs.get(cpu.op.y);
     cpu.regs.set(cpu.op.x, vx ^ vy);
+    if cpu.quirks.logic {
+        cpu.regs.set(0xF, 0);
+    }
 }
 
 fn op_8xy4(cpu: &mut CPU) {
     let vx = cpu.regs.get(cpu.op.x);
     let vy = cpu.regs.get(cpu.op.y);
-    cpu.regs.set(cpu.op.x, vx.wrapping_add(vy));
+    let (sum, carry) = vx.overflowing_add(vy);
+    set_with_flag(cpu, sum, carry as u8);
 }
 
 fn op_8xy5(cpu: &mut CPU) {
     let vx = cpu.regs.get(cpu.op.x);
     let vy = cpu.regs.get(cpu.op.y);
-    cpu.regs.set(cpu.op.x, vx.wrapping_sub(vy));
-    cpu.regs.set(0xF, if vx > vy { 1 } else { 0 });
+    set_with_flag(cpu, vx.wrapping_sub(vy), if vx >= vy { 1 } else { 0 });
 }
 
 fn op_8xy6(cpu: &mut CPU) {
     let vx = cpu.regs.get(cpu.op.x);
     let vy = cpu.regs.get(cpu.op.y);
-    cpu.regs.set(cpu.op.x, vy >> 1);
-    cpu.regs.set(0xF, vx & 0x1);
+    let source = if cpu.quirks.shift { vx } else { vy };
+    set_with_flag(cpu, source >> 1, source & 0x1);
 }
 
 fn op_8xy7(cpu: &mut CPU) {
     let vx = cpu.regs.get(cpu.op.x);
     let vy = cpu.regs.get(cpu.op.y);
-    cpu.regs.set(cpu.op.x, vy.wrapping_sub(vx));
-    cpu.regs.set(0xF, if vy > vx { 1 } else { 0 });
+    set_with_flag(cpu, vy.wrapping_sub(vx), if vy >= vx { 1 } else { 0 });
 }
 
 fn op_8xye(cpu: &mut CPU) {
     let vx = cpu.regs.get(cpu.op.x);
     let vy = cpu.regs.get(cpu.op.y);
-    cpu.regs.set(cpu.op.x, vy << 1);
-    cpu.regs.set(0xF, (vx & 0x80) >> 7);
+    let source = if cpu.quirks.shift { vx } else { vy };
+    set_with_flag(cpu, source << 1, source >> 7);
+}
+
+// VX and VF, the later write winning when X is F
+fn set_with_flag(cpu: &mut CPU, result: u8, flag: u8) {
+    if cpu.quirks.vf_order {
+        cpu.regs.set(0xF, flag);
+        cpu.regs.set(cpu.op.x, result);
+    } else {
+        cpu.regs.set(cpu.op.x, result);
+        cpu.regs.set(0xF, flag);
+    }
 }
 
 fn op_annn(cpu: &mut CPU) {
@@ -334,7 +631,33 @@ fn op_annn(cpu: &mut CPU) {
 }
 
 fn op_bnnn(cpu: &mut CPU) {
-    cpu.regs.pc = cpu.op.nnn + cpu.regs.get(0x0) as usize;
+    let offset = if cpu.quirks.jump { cpu.regs.get(cpu.op.nnn >> 8) } else { cpu.regs.get(0x0) };
+    cpu.regs.pc = cpu.op.nnn + offset as usize;
+}
+
+fn op_02a0(cpu: &mut CPU) {
+    cpu.frame_buffer.next_background();
+}
+
+// CHIP-8X colors with the color in V(X+1). BXY0 colors zones of 8x4 pixels, VX and VY giving
+// the first zone column and row in the low nibble and how many more follow in the high nibble.
+// BXYN colors N pixel rows of the 8 pixel column at VX, VY.
+fn op_bxyn(cpu: &mut CPU) {
+    let (vx, vy) = (cpu.regs.get(cpu.op.x) as usize, cpu.regs.get(cpu.op.y) as usize);
+    let color = cpu.regs.get((cpu.op.x + 1) & 0xF);
+    if cpu.op.n == 0 {
+        for row in (vy & 0xF)..=(vy & 0xF) + (vy >> 4) {
+            for column in (vx & 0xF)..=(vx & 0xF) + (vx >> 4) {
+                for y in row * 4..row * 4 + 4 {
+                    cpu.frame_buffer.set_zone_color(column, y, color);
+                }
+            }
+        }
+    } else {
+        for y in vy..vy + cpu.op.n as usize {
+            cpu.frame_buffer.set_zone_color(vx / ZONE_WIDTH, y % HEIGHT, color);
+        }
+    }
 }
 
 fn op_cxnn(cpu: &mut CPU) {
@@ -349,26 +672,18 @@ fn op_dxyn(cpu: &mut CPU) {
     let ori_y = cpu.regs.get(cpu.op.y) as usize & (HEIGHT -1); //% HEIGHT;
 
     for row in 0..value {
-        let y = ori_y + row;
-        if y >= HEIGHT {
-            break;
-        }
+        let y = match ori_y + row {
+            y if y < HEIGHT => y,
+            _ if cpu.quirks.clip => break,
+            y => y % HEIGHT,
+        };
 
         let sprite = cpu.ram.read8(cpu.regs.i + row);
-        for pixel_position in 0..8 {
-            let x = ori_x + pixel_position;
-            if x >= WIDTH {
-                break;
-            }
-
-            let memory_pixel: bool = (sprite & (1 << (7 - pixel_position))) > 0;
-            let frame_pixel: bool = cpu.frame_buffer.toggle_buffer[x][y];
-            cpu.frame_buffer.toggle_buffer[x][y] = memory_pixel ^ frame_pixel;
-            cpu.frame_buffer.update_frame_buffer(x, y);
-            vf = (memory_pixel && frame_pixel) || vf;
-        }
+        cpu.coverage.mark(cpu.regs.i + row, coverage::SPRITE);
+        vf = cpu.frame_buffer.draw_sprite_row(ori_x, y, sprite, !cpu.quirks.clip) || vf;
     }
     cpu.regs.set(0xF, if vf { 1 } else { 0 });
+    cpu.drew = true;
 }
 
 fn op_ex9e(cpu: &mut CPU) {
@@ -415,17 +730,29 @@ fn op_fx29(cpu: &mut CPU) {
     cpu.regs.i = cpu.ram.get_font_address() + char * 5;
 }
 
+fn op_fx30(cpu: &mut CPU) {
+    let char = (cpu.regs.get(cpu.op.x) & 0xF) as usize;
+    cpu.regs.i = cpu.ram.get_big_font_address() + char * 10;
+}
+
 fn op_fx33(cpu: &mut CPU) {
     let vx = cpu.regs.get(cpu.op.x);
     cpu.ram.write8(cpu.regs.i, vx / 100);
     cpu.ram.write8(cpu.regs.i + 1, vx / 10 % 10);
     cpu.ram.write8(cpu.regs.i + 2, vx % 10);
+    for offset in 0..3 {
+        cpu.coverage.mark(cpu.regs.i + offset, coverage::DATA_WRITE);
+    }
 }
 
 fn op_fx55(cpu: &mut CPU) {
     let i = cpu.regs.i;
     for regs in 0x0..(cpu.op.x + 1) {
         cpu.ram.write8(i + regs, cpu.regs.get(regs));
+        cpu.coverage.mark(i + regs, coverage::DATA_WRITE);
+    }
+    if !cpu.quirks.load_store {
+        cpu.regs.i += cpu.op.x + 1;
     }
 }
 
@@ -433,5 +760,93 @@ fn op_fx65(cpu: &mut CPU) {
     let i = cpu.regs.i;
     for regs in 0x0..(cpu.op.x + 1) {
         cpu.regs.set(regs, cpu.ram.read8(i + regs));
+        cpu.coverage.mark(i + regs, coverage::DATA_READ);
+    }
+    if !cpu.quirks.load_store {
+        cpu.regs.i += cpu.op.x + 1;
+    }
+}
+
+// SUPER-CHIP: V0 to VX go to the RPL user flags, 16 of them as on XO-CHIP
+fn op_fx75(cpu: &mut CPU) {
+    let values: Vec<u8> = (0x0..(cpu.op.x + 1)).map(|regs| cpu.regs.get(regs)).collect();
+    if let Err(e) = cpu.rpl_flags.store(&values) {
+        println!("Unable to save the RPL flags: {}", e);
+    }
+}
+
+fn op_fx85(cpu: &mut CPU) {
+    let flags = *cpu.rpl_flags.get();
+    for (regs, &flag) in flags.iter().enumerate().take(cpu.op.x + 1) {
+        cpu.regs.set(regs, flag);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip_8::assembler;
+
+    fn run(source: &str, steps: usize) -> CPU {
+        let mut cpu = CPU::new(Platform::Chip8);
+        cpu.load_rom(&assembler::assemble(source).unwrap());
+        for _ in 0..steps {
+            cpu.step();
+        }
+        cpu
+    }
+
+    #[test]
+    fn add_sets_carry() {
+        let cpu = run(": main v0 := 0xFF v1 := 2 v0 += v1 v2 := 1 v2 += v1", 5);
+        assert_eq!((cpu.regs.get(0x0), cpu.regs.get(0x2), cpu.regs.get(0xF)), (1, 3, 0));
+        let cpu = run(": main v0 := 0xFF v1 := 2 v0 += v1", 3);
+        assert_eq!(cpu.regs.get(0xF), 1);
+    }
+
+    // VY is shifted into VX and its shifted out bit goes to VF, or VX's with the shift quirk
+    #[test]
+    fn shifts_take_the_flag_from_the_source() {
+        let cpu = run(": main v0 := 0x80 v1 := 0x03 v0 >>= v1", 3);
+        assert_eq!((cpu.regs.get(0x0), cpu.regs.get(0xF)), (0x01, 1));
+        let cpu = run(": main v0 := 0x01 v1 := 0x81 v0 <<= v1", 3);
+        assert_eq!((cpu.regs.get(0x0), cpu.regs.get(0xF)), (0x02, 1));
+
+        let mut cpu = CPU::new(Platform::Chip8);
+        let mut quirks = Quirks::new();
+        quirks.shift = true;
+        cpu.set_quirks(quirks);
+        cpu.load_rom(&assembler::assemble(": main v0 := 0x80 v1 := 0x03 v0 >>= v1").unwrap());
+        for _ in 0..3 {
+            cpu.step();
+        }
+        assert_eq!((cpu.regs.get(0x0), cpu.regs.get(0xF)), (0x40, 0));
+    }
+
+    // Octo's comparisons subtract and test VF, which is set when nothing is borrowed
+    #[test]
+    fn assembled_comparisons() {
+        for operator in ["==", "!=", "<", ">", "<=", ">="].iter() {
+            for a in 4..=6 {
+                let source = format!(
+                    ": main v0 := {} v2 := 5 v1 := 0 v3 := 0
+                     if v0 {} v2 then v1 := 1
+                     if v0 {} 5 then v3 := 1
+                     loop again",
+                    a, operator, operator
+                );
+                let cpu = run(&source, 20);
+                let expected = match *operator {
+                    "==" => a == 5,
+                    "!=" => a != 5,
+                    "<" => a < 5,
+                    ">" => a > 5,
+                    "<=" => a <= 5,
+                    _ => a >= 5,
+                };
+                let taken = (cpu.regs.get(0x1) == 1, cpu.regs.get(0x3) == 1);
+                assert_eq!(taken, (expected, expected), "{} {} 5", a, operator);
+            }
+        }
     }
 }
@@ -1,45 +1,254 @@
 use sdl2::pixels::Color;
-use crate::chip_8::display;
+use crate::chip_8::display::{self, PersistenceMode, Region};
+use crate::chip_8::palette::Palette;
 
 pub const WIDTH: usize = display::WIDTH;
 pub const HEIGHT: usize = display::HEIGHT;
 pub const PITCH_BYTES: usize = display::PITCH_BYTES;
 
+pub const MAX_PHOSPHOR_FRAMES: u32 = 16;
+const MAX_DIRTY_REGIONS: usize = 8;
+
+// Each row is packed into a u64, column 0 being the most significant bit
+const _: () = assert!(WIDTH == 64);
+
+// CHIP-8X color board (VP-590): each 8x1 pixel zone has its own foreground color,
+// the background is shared. Colors are 3 bits of red, blue and green.
+pub const ZONE_WIDTH: usize = 8;
+const ZONE_COLORS: [Color; 8] = [
+    Color::RGB(0, 0, 0),
+    Color::RGB(255, 0, 0),
+    Color::RGB(0, 0, 255),
+    Color::RGB(255, 0, 255),
+    Color::RGB(0, 255, 0),
+    Color::RGB(255, 255, 0),
+    Color::RGB(0, 255, 255),
+    Color::RGB(255, 255, 255),
+];
+const BACKGROUND_COLORS: [Color; 4] = [
+    Color::RGB(0, 0, 128),
+    Color::RGB(0, 0, 0),
+    Color::RGB(0, 128, 0),
+    Color::RGB(128, 0, 0),
+];
+
+#[derive(Clone, Copy)]
+pub struct ColorZones {
+    background: usize,
+    zones: [[u8; WIDTH / ZONE_WIDTH]; HEIGHT],
+}
+
+impl ColorZones {
+    pub fn new() -> ColorZones {
+        ColorZones {
+            background: 0,
+            zones: [[1; WIDTH / ZONE_WIDTH]; HEIGHT], // Red
+        }
+    }
+}
+
 pub struct FrameBuffer {
-    pub frame_buffer: [u8; WIDTH * HEIGHT * PITCH_BYTES],
-    pub toggle_buffer: [[bool; HEIGHT]; WIDTH],
-    off_color: Color,
-    on_color: Color,
+    rows: [u64; HEIGHT],
+    lit_this_frame: [u64; HEIGHT], // Pixels that were on at any point of the current frame
+    history: [[u64; HEIGHT]; MAX_PHOSPHOR_FRAMES as usize], // Ring of lit_this_frame for past frames
+    history_head: usize,           // Index of the frame that just ended
+    dirty: Vec<Region>,            // Areas whose RGBA conversion is out of date
+    rgba: Vec<u8>,
+    palette: [Color; 4],
+    persistence: PersistenceMode,
+    color_zones: Option<ColorZones>, // Replaces the palette when set
 }
 
 impl FrameBuffer {
     pub fn new() -> FrameBuffer {
         FrameBuffer {
-            frame_buffer: [0; WIDTH * HEIGHT * PITCH_BYTES],
-            toggle_buffer: [[false; HEIGHT]; WIDTH],
-            off_color: Color::RGBA(0, 0, 0, 255),
-            on_color: Color::RGBA(255, 255, 255, 255),
+            rows: [0; HEIGHT],
+            lit_this_frame: [0; HEIGHT],
+            history: [[0; HEIGHT]; MAX_PHOSPHOR_FRAMES as usize],
+            history_head: 0,
+            dirty: vec![Region::full(WIDTH, HEIGHT)],
+            rgba: vec![0; WIDTH * HEIGHT * PITCH_BYTES],
+            palette: Palette::builtin()[0].colors,
+            persistence: PersistenceMode::Off,
+            color_zones: None,
         }
     }
 
     pub fn clear(&mut self) {
-        for (x, col) in self.toggle_buffer.iter().enumerate() {
-            for (y, _) in col.iter().enumerate() {
-                let offset = x * PITCH_BYTES + y * WIDTH * PITCH_BYTES;
-                for i in offset..(offset + PITCH_BYTES) { self.frame_buffer[i] = self.color_to_array(&self.off_color)[i - offset]; }
+        self.rows = [0; HEIGHT];
+        self.mark_dirty(Region::full(WIDTH, HEIGHT));
+    }
+
+    pub fn get_pixel(&self, x: usize, y: usize) -> bool {
+        self.rows[y] & (1 << (WIDTH - 1 - x)) != 0
+    }
+
+    // XORs 8 sprite pixels into a row, clipping or wrapping at the right edge, and returns true
+    // on collision
+    pub fn draw_sprite_row(&mut self, x: usize, y: usize, sprite: u8, wrap: bool) -> bool {
+        let bits = if wrap {
+            ((sprite as u64) << (WIDTH - 8)).rotate_right(x as u32)
+        } else {
+            ((sprite as u64) << (WIDTH - 8)) >> x
+        };
+        let collision = self.rows[y] & bits != 0;
+        self.rows[y] ^= bits;
+        self.lit_this_frame[y] |= self.rows[y];
+        if wrap && x > WIDTH - 8 {
+            self.mark_dirty(Region::new(0, y, WIDTH, 1));
+        } else {
+            self.mark_dirty(Region::new(x, y, 8.min(WIDTH - x), 1));
+        }
+        collision
+    }
+
+    pub fn get_rows(&self) -> [u64; HEIGHT] {
+        self.rows
+    }
+
+    // Takes the screen from a machine that draws it itself, keeping the fade of changed pixels
+    pub fn update_rows(&mut self, rows: [u64; HEIGHT]) {
+        for (y, &row) in rows.iter().enumerate() {
+            if self.rows[y] != row {
+                self.rows[y] = row;
+                self.lit_this_frame[y] |= row;
+                self.mark_dirty(Region::new(0, y, WIDTH, 1));
             }
         }
+    }
+
+    // Replaces the whole screen, e.g. when loading a state, without fading from the old one
+    pub fn set_rows(&mut self, rows: [u64; HEIGHT]) {
+        self.rows = rows;
+        self.lit_this_frame = rows;
+        self.history = [[0; HEIGHT]; MAX_PHOSPHOR_FRAMES as usize];
+        self.mark_dirty(Region::full(WIDTH, HEIGHT));
+    }
+
+    pub fn get_color_zones(&self) -> Option<ColorZones> {
+        self.color_zones
+    }
 
-        self.toggle_buffer = [[false; HEIGHT]; WIDTH];
+    pub fn set_color_zones(&mut self, color_zones: Option<ColorZones>) {
+        self.color_zones = color_zones;
+        self.mark_dirty(Region::full(WIDTH, HEIGHT));
     }
 
-    pub fn update_frame_buffer(&mut self, x: usize, y: usize) {
-        let offset = x * PITCH_BYTES + y * WIDTH * PITCH_BYTES;
-        let color: Color = if self.toggle_buffer[x][y] { self.on_color } else { self.off_color };
-        for i in offset..(offset + PITCH_BYTES) { self.frame_buffer[i] = self.color_to_array(&color)[i - offset]; }
+    pub fn next_background(&mut self) {
+        if let Some(color_zones) = &mut self.color_zones {
+            color_zones.background = (color_zones.background + 1) % BACKGROUND_COLORS.len();
+            self.mark_dirty(Region::full(WIDTH, HEIGHT));
+        }
     }
 
-    fn color_to_array(&self, color: &Color) -> [u8; PITCH_BYTES] {
-        [color.r, color.g, color.b, color.a]
+    // Zone column and pixel row, clipped to the screen
+    pub fn set_zone_color(&mut self, column: usize, y: usize, color: u8) {
+        if let Some(color_zones) = &mut self.color_zones {
+            if column < WIDTH / ZONE_WIDTH && y < HEIGHT {
+                color_zones.zones[y][column] = color & 7;
+                self.mark_dirty(Region::new(column * ZONE_WIDTH, y, ZONE_WIDTH, 1));
+            }
+        }
     }
+
+    pub fn set_palette(&mut self, palette: &Palette) {
+        self.palette = palette.colors;
+        self.mark_dirty(Region::full(WIDTH, HEIGHT));
+    }
+
+    pub fn set_persistence(&mut self, persistence: PersistenceMode) {
+        self.persistence = match persistence {
+            PersistenceMode::Phosphor(frames) => {
+                PersistenceMode::Phosphor(frames.clamp(1, MAX_PHOSPHOR_FRAMES))
+            }
+            mode => mode,
+        };
+        self.mark_dirty(Region::full(WIDTH, HEIGHT));
+    }
+
+    pub fn end_frame(&mut self) {
+        self.history_head = (self.history_head + 1) % self.history.len();
+        for y in 0..HEIGHT {
+            self.history[self.history_head][y] = self.lit_this_frame[y] | self.rows[y];
+            self.lit_this_frame[y] = self.rows[y];
+        }
+        if self.persistence != PersistenceMode::Off {
+            self.mark_dirty(Region::full(WIDTH, HEIGHT)); // Fading touches every pixel
+        }
+    }
+
+    fn mark_dirty(&mut self, mut region: Region) {
+        // Merge with every overlapping or touching region so the set stays small
+        while let Some(i) = self.dirty.iter().position(|r| r.touches(&region)) {
+            region = region.union(&self.dirty.swap_remove(i));
+        }
+        self.dirty.push(region);
+        if self.dirty.len() > MAX_DIRTY_REGIONS {
+            let bounds = self.dirty.iter().fold(region, |acc, r| acc.union(r));
+            self.dirty = vec![bounds];
+        }
+    }
+
+    // Converts the out of date areas to RGBA and returns them
+    pub fn render(&mut self) -> Vec<Region> {
+        let dirty = std::mem::take(&mut self.dirty);
+        for region in dirty.iter() {
+            for y in region.y..region.y + region.height {
+                for x in region.x..region.x + region.width {
+                    let color = self.color(x, y);
+                    let offset = (y * WIDTH + x) * PITCH_BYTES;
+                    self.rgba[offset..offset + PITCH_BYTES].copy_from_slice(&[color.r, color.g, color.b, color.a]);
+                }
+            }
+        }
+        dirty
+    }
+
+    pub fn get_rgba(&self) -> &[u8] {
+        &self.rgba
+    }
+
+    // Lit state of a pixel `age` frames ago, 0 being the frame that just ended
+    fn was_lit(&self, x: usize, y: usize, age: usize) -> bool {
+        let index = (self.history_head + self.history.len() - age) % self.history.len();
+        self.history[index][y] & (1 << (WIDTH - 1 - x)) != 0
+    }
+
+    // Background and foreground at a pixel
+    fn colors_at(&self, x: usize, y: usize) -> [Color; 2] {
+        match &self.color_zones {
+            Some(color_zones) => [
+                BACKGROUND_COLORS[color_zones.background],
+                ZONE_COLORS[color_zones.zones[y][x / ZONE_WIDTH] as usize],
+            ],
+            None => [self.palette[0], self.palette[1]],
+        }
+    }
+
+    fn color(&self, x: usize, y: usize) -> Color {
+        let on = self.get_pixel(x, y);
+        let colors = self.colors_at(x, y);
+        match self.persistence {
+            PersistenceMode::Off => colors[on as usize],
+            PersistenceMode::Blend => {
+                let lit = on || self.was_lit(x, y, 0) || self.was_lit(x, y, 1);
+                colors[lit as usize]
+            }
+            PersistenceMode::Phosphor(frames) => {
+                let brightness = if on {
+                    1.
+                } else {
+                    (0..frames)
+                        .find(|&age| self.was_lit(x, y, age as usize))
+                        .map_or(0., |age| (frames - age) as f32 / (frames + 1) as f32)
+                };
+                blend(colors, brightness)
+            }
+        }
+    }
+}
+
+fn blend([off, on]: [Color; 2], brightness: f32) -> Color {
+    let mix = |a: u8, b: u8| (a as f32 + (b as f32 - a as f32) * brightness) as u8;
+    Color::RGB(mix(off.r, on.r), mix(off.g, on.g), mix(off.b, on.b))
 }
@@ -1,9 +1,9 @@
-use sdl2::keyboard::Keycode;
+use sdl2::keyboard::Scancode;
 use std::collections::HashMap;
 
 pub struct Keypad {
     pub key_status: [bool; 0x10],
-    pub keys: HashMap<Keycode, usize>,
+    pub keys: HashMap<Scancode, usize>, // Physical key positions, independent of the keyboard layout
 }
 
 impl Keypad {
@@ -11,22 +11,22 @@ impl Keypad {
         Keypad {
             key_status: [false; 0x10],
             keys: [
-                (Keycode::Num1, 0x1),
-                (Keycode::Num2, 0x2),
-                (Keycode::Num3, 0x3),
-                (Keycode::Num4, 0xC),
-                (Keycode::Q, 0x4),
-                (Keycode::W, 0x5),
-                (Keycode::E, 0x6),
-                (Keycode::R, 0xD),
-                (Keycode::A, 0x7),
-                (Keycode::S, 0x8),
-                (Keycode::D, 0x9),
-                (Keycode::F, 0xE),
-                (Keycode::Z, 0xA),
-                (Keycode::X, 0x0),
-                (Keycode::C, 0xB),
-                (Keycode::V, 0xF),
+                (Scancode::Num1, 0x1),
+                (Scancode::Num2, 0x2),
+                (Scancode::Num3, 0x3),
+                (Scancode::Num4, 0xC),
+                (Scancode::Q, 0x4),
+                (Scancode::W, 0x5),
+                (Scancode::E, 0x6),
+                (Scancode::R, 0xD),
+                (Scancode::A, 0x7),
+                (Scancode::S, 0x8),
+                (Scancode::D, 0x9),
+                (Scancode::F, 0xE),
+                (Scancode::Z, 0xA),
+                (Scancode::X, 0x0),
+                (Scancode::C, 0xB),
+                (Scancode::V, 0xF),
             ]
             .iter()
             .cloned()
@@ -34,13 +34,32 @@ impl Keypad {
         }
     }
 
-    pub fn compute_keycode(&self, keycode: Keycode) -> Option<usize> {
-        if let Some(&chip8_key) = self.keys.get(&keycode) {
+    pub fn compute_scancode(&self, scancode: Scancode) -> Option<usize> {
+        if let Some(&chip8_key) = self.keys.get(&scancode) {
             return Some(chip8_key);
         }
         None
     }
 
+    // Replaces every host key of a CHIP-8 key, taking them away from other CHIP-8 keys
+    pub fn bind(&mut self, chip8_key: usize, scancodes: &[Scancode]) {
+        self.keys.retain(|_, &mut key| key != chip8_key);
+        for &scancode in scancodes {
+            self.keys.insert(scancode, chip8_key);
+        }
+    }
+
+    pub fn get_bindings(&self, chip8_key: usize) -> Vec<Scancode> {
+        let mut scancodes: Vec<Scancode> = self
+            .keys
+            .iter()
+            .filter(|(_, &key)| key == chip8_key)
+            .map(|(&scancode, _)| scancode)
+            .collect();
+        scancodes.sort_by_key(|&scancode| scancode as i32);
+        scancodes
+    }
+
     pub fn get_status(&mut self, pos: usize) -> bool {
         self.key_status[pos]
     }
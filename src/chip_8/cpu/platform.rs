@@ -0,0 +1,78 @@
+use super::fonts::SystemFont;
+
+// Machines CHIP-8 ran on, which differ in memory layout and, for CHIP-8X, in instructions
+#[derive(Clone, Copy, PartialEq)]
+pub enum Platform {
+    Chip8,
+    Eti660,
+    Dream6800,
+    Chip8x,
+    Vip, // The whole machine, running the original interpreter
+}
+
+const PLATFORMS: [(Platform, &str); 5] = [
+    (Platform::Chip8, "chip8"),
+    (Platform::Eti660, "eti660"),
+    (Platform::Dream6800, "dream6800"),
+    (Platform::Chip8x, "chip8x"),
+    (Platform::Vip, "vip"),
+];
+
+impl Platform {
+    pub fn from_name(name: &str) -> Option<Platform> {
+        PLATFORMS.iter().find(|(_, n)| *n == name).map(|(p, _)| *p)
+    }
+
+    // ROM file extensions that imply a platform
+    pub fn from_extension(extension: &str) -> Option<Platform> {
+        match extension.to_lowercase().as_str() {
+            "c8x" => Some(Platform::Chip8x),
+            _ => None,
+        }
+    }
+
+    // A DREAM 6800 came with 1 KiB and was commonly expanded to 2 KiB
+    pub fn ram_size(&self) -> usize {
+        match self {
+            Platform::Dream6800 => 0x800,
+            _ => 0x1000,
+        }
+    }
+
+    // Where programs are loaded and start, after the interpreter
+    pub fn rom_address(&self) -> usize {
+        match self {
+            Platform::Eti660 => 0x600,
+            Platform::Chip8x => 0x300,
+            _ => 0x200,
+        }
+    }
+
+    // CHIPOS keeps its digits in ROM, here they sit in its scratch page instead
+    pub fn font_address(&self) -> usize {
+        match self {
+            Platform::Dream6800 => 0x080,
+            _ => 0x050,
+        }
+    }
+
+    // Every platform but the 6800 based DREAM runs its interpreter on an 1802
+    pub fn has_1802(&self) -> bool {
+        *self != Platform::Dream6800
+    }
+
+    // Programs for the VIP's descendants may call 1802 machine code with 0NNN, elsewhere
+    // such calls are more likely SUPER-CHIP instructions than routines
+    pub fn runs_machine_code(&self) -> bool {
+        matches!(self, Platform::Eti660 | Platform::Chip8x | Platform::Vip)
+    }
+
+    pub fn font(&self) -> SystemFont {
+        match self {
+            Platform::Chip8 => SystemFont::Octo,
+            Platform::Eti660 => SystemFont::Eti660,
+            Platform::Dream6800 => SystemFont::Dream6800,
+            Platform::Chip8x | Platform::Vip => SystemFont::Vip,
+        }
+    }
+}
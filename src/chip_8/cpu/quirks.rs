@@ -0,0 +1,44 @@
+// Instructions whose behaviour differs between CHIP-8 interpreters. The defaults are this
+// emulator's own, ROMs written for another interpreter may need some of them changed.
+#[derive(Clone, Copy)]
+pub struct Quirks {
+    pub shift: bool,      // 8XY6 and 8XYE shift VX instead of VY
+    pub load_store: bool, // FX55 and FX65 leave I unchanged instead of moving it past VX
+    pub jump: bool,       // BXNN jumps to XNN + VX instead of NNN + V0
+    pub logic: bool,      // 8XY1, 8XY2 and 8XY3 clear VF
+    pub clip: bool,       // Sprites are cut at the screen edges instead of wrapping around
+    pub vblank: bool,     // DXYN waits for the next frame
+    pub vf_order: bool,   // 8XY5 to 8XYE set VF before VX, so VX wins when it is VF
+}
+
+pub const QUIRK_NAMES: [&str; 7] = ["shift", "load-store", "jump", "logic", "clip", "vblank", "vf-order"];
+
+impl Quirks {
+    pub fn new() -> Quirks {
+        Quirks {
+            shift: false,
+            load_store: true,
+            jump: false,
+            logic: false,
+            clip: true,
+            vblank: false,
+            vf_order: false,
+        }
+    }
+
+    // By one of QUIRK_NAMES, false when there is no such quirk
+    pub fn set(&mut self, name: &str, on: bool) -> bool {
+        let quirk = match name {
+            "shift" => &mut self.shift,
+            "load-store" => &mut self.load_store,
+            "jump" => &mut self.jump,
+            "logic" => &mut self.logic,
+            "clip" => &mut self.clip,
+            "vblank" => &mut self.vblank,
+            "vf-order" => &mut self.vf_order,
+            _ => return false,
+        };
+        *quirk = on;
+        true
+    }
+}
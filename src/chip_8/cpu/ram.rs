@@ -1,49 +1,50 @@
+use super::cdp1802::Bus;
+use super::fonts::{self, SystemFont};
+use super::platform::Platform;
+
+#[derive(Clone)]
 pub struct RAM {
-    ram: [u8; 4 * 1024],
+    ram: Vec<u8>,
     font_address: usize,
+    big_font_address: usize,
     rom_address: usize,
 }
 
 impl RAM {
-    pub fn new() -> RAM {
-        RAM {
-            ram: [0x00; 4 * 1024],
-            font_address: 0x50 as usize,
-            rom_address: 0x200 as usize,
-        }
+    pub fn new(platform: Platform) -> RAM {
+        let mut ram = RAM {
+            ram: vec![0x00; platform.ram_size()],
+            font_address: 0,
+            big_font_address: 0,
+            rom_address: platform.rom_address(),
+        };
+        ram.init_fonts(platform.font(), platform.font_address());
+        ram
     }
 
-    pub fn init_fonts(&mut self) {
-        let fonts: [u8; 80] = [
-            0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
-            0x20, 0x60, 0x20, 0x20, 0x70, // 1
-            0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
-            0xF0, 0x10, 0xF0, 0x10, 0xF0, // 3
-            0x90, 0x90, 0xF0, 0x10, 0x10, // 4
-            0xF0, 0x80, 0xF0, 0x10, 0xF0, // 5
-            0xF0, 0x80, 0xF0, 0x90, 0xF0, // 6
-            0xF0, 0x10, 0x20, 0x40, 0x40, // 7
-            0xF0, 0x90, 0xF0, 0x90, 0xF0, // 8
-            0xF0, 0x90, 0xF0, 0x10, 0xF0, // 9
-            0xF0, 0x90, 0xF0, 0x90, 0x90, // A
-            0xE0, 0x90, 0xE0, 0x90, 0xE0, // B
-            0xF0, 0x80, 0x80, 0x80, 0xF0, // C
-            0xE0, 0x90, 0x90, 0x90, 0xE0, // D
-            0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
-            0xF0, 0x80, 0xF0, 0x80, 0x80, // F
-        ];
-        for i in 0..fonts.len() {
-            self.ram[i + self.font_address] = fonts[i];
-        }
+    // The big font follows the small one
+    pub fn init_fonts(&mut self, font: SystemFont, address: usize) {
+        self.font_address = address;
+        self.big_font_address = address + fonts::SMALL_SIZE;
+        self.ram[self.font_address..self.big_font_address].copy_from_slice(font.small());
+        self.ram[self.big_font_address..self.big_font_address + fonts::BIG_SIZE].copy_from_slice(font.big());
     }
 
     pub fn get_font_address(&self) -> usize {
         self.font_address
     }
 
+    pub fn get_big_font_address(&self) -> usize {
+        self.big_font_address
+    }
+
+    pub fn get_rom_address(&self) -> usize {
+        self.rom_address
+    }
+
     pub fn load_rom(&mut self, rom: &[u8]) {
         assert!(
-            rom.len() <= self.ram.len() - 0x200,
+            rom.len() <= self.max_rom_size(),
             "ROM is bigger than Chip-8 RAM"
         );
         for i in 0..rom.len() {
@@ -51,33 +52,38 @@ impl RAM {
         }
     }
 
+    pub fn max_rom_size(&self) -> usize {
+        self.ram.len() - self.rom_address
+    }
+
+    pub fn len(&self) -> usize {
+        self.ram.len()
+    }
+
+    // CHIP-8 addresses wrap around the installed RAM, as they do for the 1802, so that
+    // addresses past 2 KiB on the DREAM 6800 mirror the lower half
     pub fn read8(&self, addr: usize) -> u8 {
-        assert!(
-            addr <= self.ram.len(),
-            "addr = {}, self.0.len() = {}",
-            addr,
-            self.ram.len()
-        );
-        self.ram[addr]
+        self.ram[addr % self.ram.len()]
     }
 
     pub fn read16(&self, addr: usize) -> u16 {
-        assert!(
-            addr < self.ram.len(),
-            "addr = {}, self.0.len() = {}",
-            addr,
-            self.ram.len()
-        );
-        (self.ram[addr] as u16) << 8 | self.ram[addr + 1] as u16
+        (self.read8(addr) as u16) << 8 | self.read8(addr + 1) as u16
     }
 
     pub fn write8(&mut self, addr: usize, value: u8) {
-        assert!(
-            addr < self.ram.len(),
-            "addr = {}, self.0.len() = {}",
-            addr,
-            self.ram.len()
-        );
-        self.ram[addr] = value;
+        let len = self.ram.len();
+        self.ram[addr % len] = value;
+    }
+}
+
+// As seen by the 1802, addresses wrap around the installed RAM
+impl Bus for RAM {
+    fn read(&mut self, address: u16) -> u8 {
+        self.ram[address as usize % self.ram.len()]
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        let len = self.ram.len();
+        self.ram[address as usize % len] = value;
     }
 }
@@ -1,5 +1,4 @@
-const INITIAL_PC: usize = 0x200;
-
+#[derive(Clone)]
 pub struct Registers {
     x_0: u8,
     x_1: u8,
@@ -22,7 +21,7 @@ pub struct Registers {
 }
 
 impl Registers {
-    pub fn new() -> Registers {
+    pub fn new(pc: usize) -> Registers {
         Registers {
             x_0: 0,
             x_1: 0,
@@ -40,7 +39,7 @@ impl Registers {
             x_d: 0,
             x_e: 0,
             x_f: 0,
-            pc: INITIAL_PC,
+            pc,
             i: 0,
         }
     }
@@ -99,7 +98,7 @@ impl Registers {
         self.pc -= 2;
     }
 
-    pub fn reset_pc(&mut self) {
-        self.pc = INITIAL_PC;
+    pub fn reset_pc(&mut self, pc: usize) {
+        self.pc = pc;
     }
 }
@@ -0,0 +1,307 @@
+use super::cdp1802::{Bus, Cdp1802};
+use super::fonts::SystemFont;
+use super::frame_buffer::HEIGHT;
+use super::ram::RAM;
+
+// The COSMAC VIP itself: an 1802 running the original CHIP-8 interpreter from RAM, a CDP1861
+// video chip fetching the display by DMA, the keypad latch and the Q tone output.
+
+pub const ROM_SIZE: usize = 0x200; // Monitor ROM at 8000, mirrored up to FFFF
+
+// CDP1861: 262 lines a frame of 14 machine cycles each, the 128 display lines spending 8 of
+// them on DMA. The interrupt comes 2 lines before the display and EF1 is raised for the
+// 4 lines before its start and its end.
+const LINES: usize = 262;
+const LINE_CYCLES: i32 = 14;
+const DMA_CYCLES: i32 = 8;
+const INTERRUPT_LINE: usize = 78;
+const FIRST_DISPLAY_LINE: usize = 80;
+const DISPLAY_LINES: usize = 128;
+const LINES_PER_ROW: usize = DISPLAY_LINES / HEIGHT; // The interpreter shows each row 4 times
+
+// Stand-in for the monitor ROM, holding what the interpreter uses from it: the digits for
+// FX29, found through a table of their offsets at 8100, the interrupt routine at 8146,
+// which shows the display page in RB and counts down the timer in R8.1 and the tone in R8.0,
+// and the keypad routine at 8195 that FX0A calls
+const FONT_TABLE: usize = 0x100;
+const FONT_DATA: usize = 0x1B0;
+const INTERRUPT_EXIT: usize = 0x144;
+const KEYPAD: usize = 0x195;
+const INTERRUPT_ROUTINE: [u8; 0x38] = [
+    0x72, // 8144 LDXA      restore D
+    0x70, // 8145 RET
+    0x22, // 8146 DEC R2    entry, save T and D
+    0x78, //      SAV
+    0x22, //      DEC R2
+    0x52, //      STR R2
+    0x9B, //      GHI RB    R0 and D = display page
+    0xB0, //      PHI R0
+    0xF8, 0x00, // LDI 00
+    0xA0, //      PLO R0
+    0xC4, 0xC4, 0xC4, 0xE2, 0xE2, 0xE2, 0xE2, // wait for the first display line
+    0xA0, 0xE2, 0xE2, // 8156 PLO R0, 6 cycles between DMA bursts send each row 4 times
+    0xA0, 0xE2, 0xE2, //      PLO R0
+    0xA0, 0xE2, 0x34, 0x64, // PLO R0, B1 8164 on the last row
+    0x80, 0xE2, 0x30, 0x56, // GLO R0 for the next row, BR 8156
+    0x22, // 8164 DEC R2    save DF
+    0xF8, 0x00, // LDI 00
+    0x7E, //      SHLC
+    0x52, //      STR R2
+    0x98, //      GHI R8    count the timer down
+    0x32, 0x6F, // BZ 816F
+    0xFF, 0x01, // SMI 01
+    0xB8, //      PHI R8
+    0x88, // 816F GLO R8    sound the tone while counting it down
+    0x32, 0x77, // BZ 8177
+    0x7B, //      SEQ
+    0xFF, 0x01, // SMI 01
+    0xA8, //      PLO R8
+    0x38, //      SKP
+    0x7A, // 8177 REQ
+    0x42, //      LDA R2    restore DF
+    0xF6, //      SHR
+    0x30, 0x44, // BR 8144
+];
+
+// Called with SEP RC from code running with R3 as its program counter and X = 2, R2
+// pointing at a free byte. Waits for a key to be pressed and released, then returns it in D.
+const KEYPAD_ROUTINE: [u8; 0x1B] = [
+    0xF8, 0x00, // 8195 LDI 00
+    0xAF, // 8197 PLO RF    RF.0 = key to look at
+    0x8F, // 8198 GLO RF
+    0x52, //      STR R2
+    0x62, //      OUT 2     latch the key
+    0x22, //      DEC R2
+    0x36, 0xA4, // B3 81A4  down
+    0x1F, //      INC RF
+    0x8F, //      GLO RF
+    0xFA, 0x0F, // ANI 0F
+    0x30, 0x97, // BR 8197
+    0xF8, 0xFF, // 81A4 LDI FF  debounce for about 6 ms
+    0xFF, 0x01, // 81A6 SMI 01
+    0x3A, 0xA6, // BNZ 81A6
+    0x3E, 0x98, // BN3 8198 bounced
+    0x36, 0xAC, // 81AC B3 81AC wait for the release
+    0x8F, //      GLO RF
+    0xD3, //      SEP R3
+];
+
+#[derive(Clone)]
+struct Devices {
+    rom: Vec<u8>,
+    display_on: bool, // Turned on by INP 1, off by OUT 1
+    key_latch: usize, // Set by OUT 2, EF3 tells whether that key is down
+    keys: [bool; 16],
+    ef1: bool,
+}
+
+struct VipBus<'a> {
+    devices: &'a mut Devices,
+    ram: &'a mut RAM,
+}
+
+impl Bus for VipBus<'_> {
+    fn read(&mut self, address: u16) -> u8 {
+        if address & 0x8000 != 0 {
+            self.devices.rom[address as usize % ROM_SIZE]
+        } else {
+            self.ram.read(address)
+        }
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        if address & 0x8000 == 0 {
+            self.ram.write(address, value);
+        }
+    }
+
+    fn output(&mut self, port: u8, value: u8) {
+        match port {
+            1 => self.devices.display_on = false,
+            2 => self.devices.key_latch = (value & 0xF) as usize,
+            _ => {}
+        }
+    }
+
+    fn input(&mut self, port: u8) -> u8 {
+        if port == 1 {
+            self.devices.display_on = true;
+        }
+        0
+    }
+
+    fn flag(&mut self, flag: usize) -> bool {
+        match flag {
+            0 => self.devices.ef1,
+            2 => self.devices.keys[self.devices.key_latch],
+            _ => false,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Vip {
+    cosmac: Cdp1802,
+    devices: Devices,
+    has_monitor: bool,
+    cycle_debt: i32, // Cycles the last instruction ran past its line
+    rows: [u64; HEIGHT],
+}
+
+impl Vip {
+    // Without a monitor ROM the stand-in is used and the interpreter starts straight away,
+    // as it does when the VIP is switched to RUN without holding C
+    pub fn new(monitor: Option<&[u8]>, ram_size: usize) -> Vip {
+        let rom = match monitor {
+            Some(monitor) => {
+                let mut rom = monitor.to_vec();
+                rom.resize(ROM_SIZE, 0);
+                rom
+            }
+            None => {
+                let mut rom = vec![0; ROM_SIZE];
+                let font = SystemFont::Vip.small();
+                for digit in 0..16 {
+                    rom[FONT_TABLE + digit] = (FONT_DATA + digit * 5) as u8;
+                }
+                rom[FONT_DATA..FONT_DATA + font.len()].copy_from_slice(font);
+                rom[INTERRUPT_EXIT..INTERRUPT_EXIT + INTERRUPT_ROUTINE.len()].copy_from_slice(&INTERRUPT_ROUTINE);
+                rom[KEYPAD..KEYPAD + KEYPAD_ROUTINE.len()].copy_from_slice(&KEYPAD_ROUTINE);
+                rom
+            }
+        };
+        let mut vip = Vip {
+            cosmac: Cdp1802::new(),
+            devices: Devices {
+                rom,
+                display_on: false,
+                key_latch: 0,
+                keys: [false; 16],
+                ef1: false,
+            },
+            has_monitor: monitor.is_some(),
+            cycle_debt: 0,
+            rows: [0; HEIGHT],
+        };
+        vip.reset(ram_size);
+        vip
+    }
+
+    // The interpreter expects the page above the last one of RAM in R1, which the monitor
+    // finds by probing memory
+    pub fn reset(&mut self, ram_size: usize) {
+        self.cosmac = Cdp1802::new();
+        if self.has_monitor {
+            self.cosmac.r[0] = 0x8000;
+        } else {
+            self.cosmac.r[1] = ram_size.saturating_sub(0x100) as u16;
+        }
+        self.devices.display_on = false;
+        self.cycle_debt = 0;
+        self.rows = [0; HEIGHT];
+    }
+
+    pub fn run_frame(&mut self, ram: &mut RAM, keys: [bool; 16]) {
+        self.devices.keys = keys;
+        let mut line_bytes = [0u8; 8];
+        for line in 0..LINES {
+            let display = self.devices.display_on;
+            let display_line = line.wrapping_sub(FIRST_DISPLAY_LINE);
+            let before = |edge: usize| line < edge && line + 4 >= edge;
+            self.devices.ef1 = display && (before(FIRST_DISPLAY_LINE) || before(FIRST_DISPLAY_LINE + DISPLAY_LINES));
+            if display && line == INTERRUPT_LINE && self.cosmac.interrupt() {
+                self.cycle_debt += 1;
+            }
+            let dma = display && display_line < DISPLAY_LINES;
+            self.run(ram, if dma { LINE_CYCLES - DMA_CYCLES } else { LINE_CYCLES });
+            if dma {
+                let mut bus = VipBus {
+                    devices: &mut self.devices,
+                    ram,
+                };
+                for byte in line_bytes.iter_mut() {
+                    *byte = self.cosmac.dma_out(&mut bus);
+                }
+                if display_line % LINES_PER_ROW == 0 {
+                    self.rows[display_line / LINES_PER_ROW] = u64::from_be_bytes(line_bytes);
+                }
+            }
+        }
+        if !self.devices.display_on {
+            self.rows = [0; HEIGHT];
+        }
+    }
+
+    fn run(&mut self, ram: &mut RAM, cycles: i32) {
+        let mut bus = VipBus {
+            devices: &mut self.devices,
+            ram,
+        };
+        let mut budget = cycles - self.cycle_debt;
+        while budget > 0 {
+            budget -= self.cosmac.step(&mut bus) as i32;
+        }
+        self.cycle_debt = -budget;
+    }
+
+    pub fn get_rows(&self) -> [u64; HEIGHT] {
+        self.rows
+    }
+
+    pub fn is_tone_on(&self) -> bool {
+        self.cosmac.q
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip_8::cpu::platform::Platform;
+
+    #[test]
+    fn stand_in_monitor_layout() {
+        assert_eq!(INTERRUPT_EXIT + INTERRUPT_ROUTINE.len(), 0x17C);
+        assert_eq!(KEYPAD + KEYPAD_ROUTINE.len(), FONT_DATA);
+        assert_eq!(FONT_DATA + 16 * 5, ROM_SIZE);
+    }
+
+    // FX0A as the interpreter runs it: SEP RC to 8195 from R3, then the key goes to VX at R6
+    #[test]
+    fn fx0a_waits_for_a_key() {
+        let mut ram = RAM::new(Platform::Vip);
+        let program = [
+            0xF8, 0x0F, 0xB2, // LDI 0F, PHI R2   stack at 0F00
+            0xF8, 0x0E, 0xB6, // LDI 0E, PHI R6   VX at 0E00
+            0xF8, 0x10, 0xA3, // LDI 10, PLO R3
+            0xD3, //             SEP R3
+        ];
+        let handler = [
+            0xF8, 0x81, 0xBC, 0xF8, 0x95, 0xAC, // RC = 8195
+            0xE2, 0x22, 0xDC, //                  SEX 2, DEC R2, SEP RC
+            0x12, 0x56, //                        INC R2, STR R6
+            0x00, //                              IDL
+        ];
+        for (address, &byte) in program.iter().enumerate() {
+            ram.write8(address, byte);
+        }
+        for (offset, &byte) in handler.iter().enumerate() {
+            ram.write8(0x10 + offset, byte);
+        }
+        ram.write8(0xE00, 0xFF);
+
+        let mut vip = Vip::new(None, ram.len());
+        let mut keys = [false; 16];
+        vip.run_frame(&mut ram, keys);
+        assert!(!vip.cosmac.idle);
+        keys[0xA] = true;
+        for _ in 0..3 {
+            vip.run_frame(&mut ram, keys);
+        }
+        assert!(!vip.cosmac.idle, "returned before the key was released");
+        assert_eq!(ram.read8(0xE00), 0xFF);
+        vip.run_frame(&mut ram, [false; 16]);
+        assert!(vip.cosmac.idle);
+        assert_eq!(ram.read8(0xE00), 0xA);
+        assert_eq!(vip.cosmac.r[2], 0xF00);
+    }
+}
@@ -0,0 +1,110 @@
+use serde_json::Value;
+use std::collections::HashMap;
+
+// Titles of known ROMs by the SHA-1 of their contents, from the programs.json of the CHIP-8
+// program database (github.com/chip-8/chip-8-database): an array of programs, each with a
+// "title" and its ROM variants in "roms", keyed by their SHA-1
+pub struct Database {
+    titles: HashMap<String, String>,
+}
+
+impl Database {
+    pub fn new() -> Database {
+        Database {
+            titles: HashMap::new(),
+        }
+    }
+
+    pub fn load(filename: &str) -> Result<Database, String> {
+        let json = std::fs::read_to_string(filename).map_err(|e| format!("unable to read {}: {}", filename, e))?;
+        let programs: Value =
+            serde_json::from_str(&json).map_err(|e| format!("{} is not a program database: {}", filename, e))?;
+        let programs = programs
+            .as_array()
+            .ok_or(format!("{} is not a program database: expected a list of programs", filename))?;
+        let mut titles = HashMap::new();
+        for program in programs {
+            let (title, roms) = match (program["title"].as_str(), program["roms"].as_object()) {
+                (Some(title), Some(roms)) => (title, roms),
+                _ => continue,
+            };
+            for sha1 in roms.keys() {
+                titles.insert(sha1.to_lowercase(), title.to_string());
+            }
+        }
+        Ok(Database { titles })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.titles.is_empty()
+    }
+
+    pub fn get_title(&self, rom: &[u8]) -> Option<&str> {
+        let digest: String = sha1(rom).iter().map(|byte| format!("{:02x}", byte)).collect();
+        self.titles.get(&digest).map(|title| title.as_str())
+    }
+}
+
+fn sha1(data: &[u8]) -> [u8; 20] {
+    let mut h: [u32; 5] = [0x6745_2301, 0xEFCD_AB89, 0x98BA_DCFE, 0x1032_5476, 0xC3D2_E1F0];
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend_from_slice(&(data.len() as u64 * 8).to_be_bytes());
+
+    for block in message.chunks(64) {
+        let mut w = [0u32; 80];
+        for (i, word) in block.chunks(4).enumerate() {
+            w[i] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+        }
+        for i in 16..80 {
+            w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1);
+        }
+        let [mut a, mut b, mut c, mut d, mut e] = h;
+        for (i, &word) in w.iter().enumerate() {
+            let (f, k) = match i / 20 {
+                0 => ((b & c) | (!b & d), 0x5A82_7999),
+                1 => (b ^ c ^ d, 0x6ED9_EBA1),
+                2 => ((b & c) | (b & d) | (c & d), 0x8F1B_BCDC),
+                _ => (b ^ c ^ d, 0xCA62_C1D6),
+            };
+            let temp = a.rotate_left(5).wrapping_add(f).wrapping_add(e).wrapping_add(k).wrapping_add(word);
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = temp;
+        }
+        for (state, value) in h.iter_mut().zip([a, b, c, d, e]) {
+            *state = state.wrapping_add(value);
+        }
+    }
+
+    let mut digest = [0u8; 20];
+    for (bytes, state) in digest.chunks_mut(4).zip(h.iter()) {
+        bytes.copy_from_slice(&state.to_be_bytes());
+    }
+    digest
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex_digest(data: &[u8]) -> String {
+        sha1(data).iter().map(|byte| format!("{:02x}", byte)).collect()
+    }
+
+    #[test]
+    fn sha1_digests() {
+        assert_eq!(hex_digest(b""), "da39a3ee5e6b4b0d3255bfef95601890afd80709");
+        assert_eq!(hex_digest(b"abc"), "a9993e364706816aba3e25717850c26c9cd0d89d");
+        // Too long for the length to fit in the first block
+        assert_eq!(
+            hex_digest(b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq"),
+            "84983e441c3bd26ebaae4aa1f95129e5e54670f1"
+        );
+    }
+}
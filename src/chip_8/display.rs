@@ -1,35 +1,222 @@
-use sdl2::pixels::PixelFormatEnum;
-use sdl2::render::Canvas;
-use sdl2::video::Window;
+use super::font;
+
+use sdl2::pixels::{Color, PixelFormatEnum};
+use sdl2::rect::Rect;
+use sdl2::render::{BlendMode, Canvas, Texture, TextureCreator};
+use sdl2::video::{FullscreenType, Window, WindowContext};
 
 pub const WIDTH: usize = 64;
 pub const HEIGHT: usize = 32;
 pub const PITCH_BYTES: usize = std::mem::size_of::<u32>(); // 4 bytes: R G B A, from colors
 
+#[derive(Clone, Copy, PartialEq)]
+pub struct Region {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Region {
+    pub fn new(x: usize, y: usize, width: usize, height: usize) -> Region {
+        Region { x, y, width, height }
+    }
+
+    pub fn full(width: usize, height: usize) -> Region {
+        Region::new(0, 0, width, height)
+    }
+
+    // Overlapping or sharing an edge
+    pub fn touches(&self, other: &Region) -> bool {
+        self.x <= other.x + other.width
+            && other.x <= self.x + self.width
+            && self.y <= other.y + other.height
+            && other.y <= self.y + self.height
+    }
+
+    pub fn union(&self, other: &Region) -> Region {
+        let (x, y) = (self.x.min(other.x), self.y.min(other.y));
+        let right = (self.x + self.width).max(other.x + other.width);
+        let bottom = (self.y + self.height).max(other.y + other.height);
+        Region::new(x, y, right - x, bottom - y)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum PersistenceMode {
+    Off,
+    Blend,         // A pixel lit in this or the previous frame stays lit
+    Phosphor(u32), // A pixel fades out over the given number of frames
+}
+
+impl PersistenceMode {
+    pub fn from_name(name: &str, phosphor_frames: u32) -> Option<PersistenceMode> {
+        match name {
+            "off" => Some(PersistenceMode::Off),
+            "blend" => Some(PersistenceMode::Blend),
+            "phosphor" => Some(PersistenceMode::Phosphor(phosphor_frames)),
+            _ => None,
+        }
+    }
+
+    pub fn next(&self, phosphor_frames: u32) -> PersistenceMode {
+        match self {
+            PersistenceMode::Off => PersistenceMode::Blend,
+            PersistenceMode::Blend => PersistenceMode::Phosphor(phosphor_frames),
+            PersistenceMode::Phosphor(_) => PersistenceMode::Off,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        match self {
+            PersistenceMode::Off => "off",
+            PersistenceMode::Blend => "blend",
+            PersistenceMode::Phosphor(_) => "phosphor",
+        }
+    }
+}
+
 pub struct Display {
-    canvas: Canvas<Window>
+    canvas: Canvas<Window>,
+    texture_creator: TextureCreator<WindowContext>,
+    texture: Texture,
+    texture_size: (usize, usize),
+    logical_size: (usize, usize), // Emulated resolution, before any upscaling filter
+    pub integer_scaling: bool,
 }
 
 impl Display {
-    pub fn init(sdl_context: &sdl2::Sdl) -> Display {
+    pub fn init(sdl_context: &sdl2::Sdl, vsync: bool, integer_scaling: bool, fullscreen: bool) -> Display {
         let scale = 10;
-        let canvas = sdl_context.video().unwrap()
+        let mut window = sdl_context.video().unwrap()
             .window("Chip-8", WIDTH as u32 * scale, HEIGHT as u32 * scale)
-            .resizable().position_centered().build().unwrap()
-            .into_canvas().build().unwrap();
+            .resizable().position_centered().build().unwrap();
+        if fullscreen {
+            window.set_fullscreen(FullscreenType::Desktop).unwrap();
+        }
+        let mut canvas_builder = window.into_canvas();
+        if vsync {
+            canvas_builder = canvas_builder.present_vsync();
+        }
+        let canvas = canvas_builder.build().unwrap();
+        let texture_creator = canvas.texture_creator();
+        let texture = Display::create_texture(&texture_creator, WIDTH, HEIGHT);
 
         Display {
-            canvas: canvas
+            canvas,
+            texture_creator,
+            texture,
+            texture_size: (WIDTH, HEIGHT),
+            logical_size: (WIDTH, HEIGHT),
+            integer_scaling,
         }
     }
 
-    pub fn draw(self: &mut Display, frame_buffer: &[u8; HEIGHT * WIDTH * PITCH_BYTES]) {
-        let texture_creator = self.canvas.texture_creator();
-        let mut texture = texture_creator
-            .create_texture_streaming(PixelFormatEnum::RGBA32, WIDTH as u32, HEIGHT as u32)
-            .unwrap();
-        texture.update(None, frame_buffer, WIDTH * PITCH_BYTES).unwrap();
-        self.canvas.copy(&texture, None, None).unwrap();
+    fn create_texture(texture_creator: &TextureCreator<WindowContext>, width: usize, height: usize) -> Texture {
+        texture_creator
+            .create_texture_streaming(PixelFormatEnum::RGBA32, width as u32, height as u32)
+            .unwrap()
+    }
+
+    // Current integer window scale relative to the emulated resolution
+    pub fn get_scale(&self) -> u32 {
+        let (width, height) = self.canvas.window().size();
+        (width / self.logical_size.0 as u32).min(height / self.logical_size.1 as u32).max(1)
+    }
+
+    pub fn is_fullscreen(&self) -> bool {
+        self.canvas.window().fullscreen_state() != FullscreenType::Off
+    }
+
+    pub fn toggle_fullscreen(&mut self) -> bool {
+        let window = self.canvas.window_mut();
+        let fullscreen = window.fullscreen_state() == FullscreenType::Off;
+        let state = if fullscreen { FullscreenType::Desktop } else { FullscreenType::Off };
+        window.set_fullscreen(state).unwrap();
+        fullscreen
+    }
+
+    // Uploads a new frame, recreating the texture when the resolution changes
+    pub fn draw(self: &mut Display, frame_buffer: &[u8], width: usize, height: usize) {
+        if self.texture_size != (width, height) {
+            let texture = Display::create_texture(&self.texture_creator, width, height);
+            let old = std::mem::replace(&mut self.texture, texture);
+            unsafe { old.destroy() };
+            self.texture_size = (width, height);
+        }
+        self.texture.update(None, frame_buffer, width * PITCH_BYTES).unwrap();
+    }
+
+    // Uploads only the given regions of a frame with the same resolution as the texture
+    pub fn update(&mut self, frame_buffer: &[u8], regions: &[Region]) {
+        let pitch = self.texture_size.0 * PITCH_BYTES;
+        for region in regions {
+            let rect = Rect::new(region.x as i32, region.y as i32, region.width as u32, region.height as u32);
+            let offset = region.y * pitch + region.x * PITCH_BYTES;
+            self.texture.update(rect, &frame_buffer[offset..], pitch).unwrap();
+        }
+    }
+
+    // Draws the game; overlays are drawn on top before presenting
+    pub fn render(&mut self) {
+        let destination = self.destination();
+        self.canvas.set_draw_color(Color::RGB(0, 0, 0));
+        self.canvas.clear();
+        self.canvas.copy(&self.texture, None, destination).unwrap();
+    }
+
+    pub fn present(&mut self) {
         self.canvas.present();
     }
+
+    pub fn window_size(&self) -> (u32, u32) {
+        self.canvas.output_size().unwrap()
+    }
+
+    // Colors with an alpha below 255 are blended over the game
+    pub fn fill_rect(&mut self, rect: Rect, color: Color) {
+        self.canvas.set_blend_mode(if color.a < 255 { BlendMode::Blend } else { BlendMode::None });
+        self.canvas.set_draw_color(color);
+        self.canvas.fill_rect(rect).unwrap();
+    }
+
+    // Text in the built-in font, each font pixel being a scale x scale square
+    pub fn draw_text(&mut self, x: i32, y: i32, scale: u32, text: &str, color: Color) {
+        self.canvas.set_blend_mode(BlendMode::None);
+        self.canvas.set_draw_color(color);
+        let mut pixels = vec![];
+        for (i, c) in text.chars().enumerate() {
+            let left = x + (i as u32 * font::GLYPH_WIDTH * scale) as i32;
+            for (column, bits) in font::glyph(c).iter().enumerate() {
+                for row in 0..font::GLYPH_HEIGHT {
+                    if bits & (1 << row) != 0 {
+                        pixels.push(Rect::new(
+                            left + (column as u32 * scale) as i32,
+                            y + (row * scale) as i32,
+                            scale,
+                            scale,
+                        ));
+                    }
+                }
+            }
+        }
+        self.canvas.fill_rects(&pixels).unwrap();
+    }
+
+    // Largest aspect-correct area, in whole multiples of the emulated resolution when integer scaling
+    fn destination(&self) -> Rect {
+        let (window_width, window_height) = self.canvas.output_size().unwrap();
+        let (width, height) = (self.logical_size.0 as f64, self.logical_size.1 as f64);
+        let mut scale = (window_width as f64 / width).min(window_height as f64 / height);
+        if self.integer_scaling && scale >= 1. {
+            scale = scale.floor();
+        }
+        let (w, h) = ((width * scale) as u32, (height * scale) as u32);
+        Rect::new(
+            ((window_width - w) / 2) as i32,
+            ((window_height - h) / 2) as i32,
+            w.max(1),
+            h.max(1),
+        )
+    }
 }
@@ -0,0 +1,262 @@
+use super::image;
+
+// Effects are drawn at this multiple of the native resolution
+const EFFECT_SCALE: usize = 4;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Filter {
+    None,
+    Scanlines,
+    PixelGrid,
+    Glow,
+    Crt, // Curvature, scanlines and glow
+    Scale2x,
+    Scale3x,
+    Epx,
+}
+
+const FILTERS: [(Filter, &str); 8] = [
+    (Filter::None, "none"),
+    (Filter::Scanlines, "scanlines"),
+    (Filter::PixelGrid, "grid"),
+    (Filter::Glow, "glow"),
+    (Filter::Crt, "crt"),
+    (Filter::Scale2x, "scale2x"),
+    (Filter::Scale3x, "scale3x"),
+    (Filter::Epx, "epx"),
+];
+
+impl Filter {
+    pub fn from_name(name: &str) -> Option<Filter> {
+        FILTERS.iter().find(|(_, n)| *n == name).map(|(f, _)| *f)
+    }
+
+    pub fn name(&self) -> &str {
+        FILTERS.iter().find(|(f, _)| f == self).map(|(_, n)| *n).unwrap()
+    }
+
+    pub fn next(&self) -> Filter {
+        let index = FILTERS.iter().position(|(f, _)| f == self).unwrap();
+        FILTERS[(index + 1) % FILTERS.len()].0
+    }
+
+    // Returns the filtered RGBA image with its new width and height
+    pub fn apply(&self, rgba: &[u8], width: usize, height: usize) -> (Vec<u8>, usize, usize) {
+        let (w, h) = (width * EFFECT_SCALE, height * EFFECT_SCALE);
+        match self {
+            Filter::None => (rgba.to_vec(), width, height),
+            Filter::Scanlines => {
+                let mut scaled = image::scale_rgba(rgba, width, height, EFFECT_SCALE);
+                scanlines(&mut scaled, w, h);
+                (scaled, w, h)
+            }
+            Filter::PixelGrid => {
+                let mut scaled = image::scale_rgba(rgba, width, height, EFFECT_SCALE);
+                pixel_grid(&mut scaled, w, h);
+                (scaled, w, h)
+            }
+            Filter::Glow => {
+                let mut scaled = image::scale_rgba(rgba, width, height, EFFECT_SCALE);
+                glow(&mut scaled, w, h);
+                (scaled, w, h)
+            }
+            Filter::Crt => {
+                let mut scaled = image::scale_rgba(rgba, width, height, EFFECT_SCALE);
+                scanlines(&mut scaled, w, h);
+                glow(&mut scaled, w, h);
+                (curvature(&scaled, w, h), w, h)
+            }
+            Filter::Scale2x => (scale2x(rgba, width, height), width * 2, height * 2),
+            Filter::Scale3x => (scale3x(rgba, width, height), width * 3, height * 3),
+            Filter::Epx => (epx(rgba, width, height), width * 2, height * 2),
+        }
+    }
+}
+
+fn darken(pixel: &mut [u8], factor: f32) {
+    for channel in pixel[0..3].iter_mut() {
+        *channel = (*channel as f32 * factor) as u8;
+    }
+}
+
+fn scanlines(rgba: &mut [u8], width: usize, height: usize) {
+    for y in 0..height {
+        let factor = match y % EFFECT_SCALE {
+            2 => 0.8,
+            3 => 0.45,
+            _ => continue,
+        };
+        for x in 0..width {
+            darken(&mut rgba[(y * width + x) * 4..(y * width + x) * 4 + 4], factor);
+        }
+    }
+}
+
+fn pixel_grid(rgba: &mut [u8], width: usize, height: usize) {
+    for y in 0..height {
+        for x in 0..width {
+            if x % EFFECT_SCALE == EFFECT_SCALE - 1 || y % EFFECT_SCALE == EFFECT_SCALE - 1 {
+                darken(&mut rgba[(y * width + x) * 4..(y * width + x) * 4 + 4], 0.6);
+            }
+        }
+    }
+}
+
+// Adds a blurred copy of the image on top of itself
+fn glow(rgba: &mut [u8], width: usize, height: usize) {
+    const RADIUS: isize = 3;
+    let blur_pass = |source: &[u8], horizontal: bool| -> Vec<u8> {
+        let mut blurred = vec![0; source.len()];
+        for y in 0..height as isize {
+            for x in 0..width as isize {
+                let mut sum = [0u32; 3];
+                for d in -RADIUS..=RADIUS {
+                    let (sx, sy) = if horizontal { (x + d, y) } else { (x, y + d) };
+                    let sx = sx.clamp(0, width as isize - 1) as usize;
+                    let sy = sy.clamp(0, height as isize - 1) as usize;
+                    let offset = (sy * width + sx) * 4;
+                    for c in 0..3 {
+                        sum[c] += source[offset + c] as u32;
+                    }
+                }
+                let offset = (y as usize * width + x as usize) * 4;
+                for c in 0..3 {
+                    blurred[offset + c] = (sum[c] / (2 * RADIUS as u32 + 1)) as u8;
+                }
+            }
+        }
+        blurred
+    };
+    let blurred = blur_pass(&blur_pass(rgba, true), false);
+    for (pixel, halo) in rgba.chunks_mut(4).zip(blurred.chunks(4)) {
+        for c in 0..3 {
+            pixel[c] = pixel[c].saturating_add(halo[c] / 2);
+        }
+    }
+}
+
+// Barrel distortion with a vignette towards the edges
+fn curvature(rgba: &[u8], width: usize, height: usize) -> Vec<u8> {
+    const BULGE: f32 = 0.08;
+    let mut curved = vec![0; rgba.len()];
+    for y in 0..height {
+        for x in 0..width {
+            let nx = x as f32 / width as f32 * 2. - 1.;
+            let ny = y as f32 / height as f32 * 2. - 1.;
+            let r2 = nx * nx + ny * ny;
+            let (sx, sy) = (nx * (1. + BULGE * r2), ny * (1. + BULGE * r2));
+            let offset = (y * width + x) * 4;
+            curved[offset + 3] = 255;
+            if sx.abs() > 1. || sy.abs() > 1. {
+                continue;
+            }
+            let source_x = (((sx + 1.) / 2. * width as f32) as usize).min(width - 1);
+            let source_y = (((sy + 1.) / 2. * height as f32) as usize).min(height - 1);
+            let source = (source_y * width + source_x) * 4;
+            let vignette = 1. - 0.35 * r2 / 2.;
+            for c in 0..3 {
+                curved[offset + c] = (rgba[source + c] as f32 * vignette) as u8;
+            }
+        }
+    }
+    curved
+}
+
+// Neighbourhood of a pixel, clamped at the image edges:
+// a b c
+// d e f
+// g h i
+fn neighbours(rgba: &[u8], width: usize, height: usize, x: usize, y: usize) -> [[u8; 4]; 9] {
+    let mut n = [[0; 4]; 9];
+    for (i, (dx, dy)) in [
+        (-1, -1), (0, -1), (1, -1),
+        (-1, 0), (0, 0), (1, 0),
+        (-1, 1), (0, 1), (1, 1),
+    ]
+    .iter()
+    .enumerate()
+    {
+        let sx = (x as isize + dx).clamp(0, width as isize - 1) as usize;
+        let sy = (y as isize + dy).clamp(0, height as isize - 1) as usize;
+        let offset = (sy * width + sx) * 4;
+        n[i].copy_from_slice(&rgba[offset..offset + 4]);
+    }
+    n
+}
+
+// Runs `block` for every source pixel and writes the factor x factor block it returns
+fn upscale<F>(rgba: &[u8], width: usize, height: usize, factor: usize, block: F) -> Vec<u8>
+where
+    F: Fn(&[[u8; 4]; 9]) -> Vec<[u8; 4]>,
+{
+    let out_width = width * factor;
+    let mut scaled = vec![0; rgba.len() * factor * factor];
+    for y in 0..height {
+        for x in 0..width {
+            let pixels = block(&neighbours(rgba, width, height, x, y));
+            for (i, pixel) in pixels.iter().enumerate() {
+                let (ox, oy) = (x * factor + i % factor, y * factor + i / factor);
+                let offset = (oy * out_width + ox) * 4;
+                scaled[offset..offset + 4].copy_from_slice(pixel);
+            }
+        }
+    }
+    scaled
+}
+
+fn scale2x(rgba: &[u8], width: usize, height: usize) -> Vec<u8> {
+    upscale(rgba, width, height, 2, |n| {
+        let [_, b, _, d, e, f, _, h, _] = *n;
+        if b != h && d != f {
+            vec![
+                if d == b { d } else { e },
+                if b == f { f } else { e },
+                if d == h { d } else { e },
+                if h == f { f } else { e },
+            ]
+        } else {
+            vec![e; 4]
+        }
+    })
+}
+
+fn scale3x(rgba: &[u8], width: usize, height: usize) -> Vec<u8> {
+    upscale(rgba, width, height, 3, |n| {
+        let [a, b, c, d, e, f, g, h, i] = *n;
+        if b != h && d != f {
+            vec![
+                if d == b { d } else { e },
+                if (d == b && e != c) || (b == f && e != a) { b } else { e },
+                if b == f { f } else { e },
+                if (d == b && e != g) || (d == h && e != a) { d } else { e },
+                e,
+                if (b == f && e != i) || (h == f && e != c) { f } else { e },
+                if d == h { d } else { e },
+                if (d == h && e != i) || (h == f && e != g) { h } else { e },
+                if h == f { f } else { e },
+            ]
+        } else {
+            vec![e; 9]
+        }
+    })
+}
+
+// The original EPX formulation, which differs from Scale2x when three or more neighbours match
+fn epx(rgba: &[u8], width: usize, height: usize) -> Vec<u8> {
+    upscale(rgba, width, height, 2, |n| {
+        let (a, c, p, b, d) = (n[1], n[3], n[4], n[5], n[7]);
+        let matching = [a == b, a == c, a == d, b == c, b == d, c == d]
+            .iter()
+            .filter(|&&m| m)
+            .count();
+        if matching >= 3 {
+            return vec![p; 4];
+        }
+        vec![
+            if c == a { a } else { p },
+            if a == b { b } else { p },
+            if d == c { c } else { p },
+            if b == d { d } else { p },
+        ]
+    })
+}
@@ -0,0 +1,37 @@
+use super::audio::{Beeper, BufferBeeper, SAMPLE_RATE};
+use super::config::Config;
+use super::cpu::cpu::{CPU, FRAMES_PER_SECOND};
+use super::recorder::Recorder;
+use super::wav;
+
+// Runs a fixed number of emulated frames without a window or audio device
+pub fn run(config: &Config, cpu: &mut CPU, frames: u32) {
+    let mut beeper = BufferBeeper::new(config.tone, FRAMES_PER_SECOND);
+    let mut recorder = config.record.as_ref().and_then(|filename| {
+        Recorder::start_or_report(filename, config.record_scale, config.tone)
+    });
+
+    for _ in 0..frames {
+        cpu.run_frame();
+        cpu.render_frame_buffer();
+        beeper.set_playing(cpu.is_sound_playing());
+        beeper.render_frame();
+        if let Some(r) = &mut recorder {
+            if let Err(e) = r.capture_frame(cpu) {
+                println!("Recording stopped: {}", e);
+                recorder = None;
+            }
+        }
+    }
+
+    if let Some(recorder) = recorder {
+        recorder.finish();
+    }
+
+    if let Some(filename) = &config.wav {
+        match wav::write_wav(filename, SAMPLE_RATE as u32, &beeper.samples) {
+            Ok(()) => println!("Audio written to {}", filename),
+            Err(e) => println!("Unable to write {}: {}", filename, e),
+        }
+    }
+}
@@ -0,0 +1,100 @@
+use sdl2::keyboard::Keycode;
+use std::collections::HashMap;
+
+// Emulator commands, as opposed to CHIP-8 keys
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Action {
+    Quit,
+    Reset,
+    SpeedUp,
+    SpeedDown,
+    Pause,
+    Menu,
+    SaveState,
+    LoadState,
+    NextSlot,
+    Screenshot,
+    Record,
+    Palette,
+    Persistence,
+    Filter,
+    Fullscreen,
+    IntegerScaling,
+    Keypad,
+    StatusLine,
+    OpenRom,
+    RebindKeys,
+    Mute,
+}
+
+// Action, config name, default keys
+const ACTIONS: [(Action, &str, &[Keycode]); 21] = [
+    (Action::Quit, "quit", &[Keycode::Escape]),
+    (Action::Reset, "reset", &[Keycode::Backspace]),
+    (Action::SpeedUp, "speed-up", &[Keycode::RightBracket]),
+    (Action::SpeedDown, "speed-down", &[Keycode::LeftBracket]),
+    (Action::Pause, "pause", &[Keycode::Space]),
+    (Action::Menu, "menu", &[Keycode::Tab]),
+    (Action::SaveState, "save-state", &[Keycode::F5]),
+    (Action::LoadState, "load-state", &[Keycode::F7]),
+    (Action::NextSlot, "next-slot", &[Keycode::F6]),
+    (Action::Screenshot, "screenshot", &[Keycode::F12]),
+    (Action::Record, "record", &[Keycode::F9]),
+    (Action::Palette, "palette", &[Keycode::P]),
+    (Action::Persistence, "persistence", &[Keycode::O]),
+    (Action::Filter, "filter", &[Keycode::G]),
+    (Action::Fullscreen, "fullscreen", &[Keycode::F11]),
+    (Action::IntegerScaling, "integer-scaling", &[Keycode::I]),
+    (Action::Keypad, "keypad", &[Keycode::K]),
+    (Action::StatusLine, "status-line", &[Keycode::F3]),
+    (Action::OpenRom, "open-rom", &[Keycode::F4]),
+    (Action::RebindKeys, "rebind-keys", &[Keycode::F2]),
+    (Action::Mute, "mute", &[Keycode::M]),
+];
+
+impl Action {
+    pub fn from_name(name: &str) -> Option<Action> {
+        ACTIONS.iter().find(|(_, n, _)| *n == name).map(|(a, _, _)| *a)
+    }
+}
+
+#[derive(Clone)]
+pub struct Hotkeys {
+    keys: HashMap<Keycode, Action>,
+}
+
+impl Hotkeys {
+    pub fn new() -> Hotkeys {
+        let mut keys = HashMap::new();
+        for (action, _, defaults) in ACTIONS.iter() {
+            for &keycode in defaults.iter() {
+                keys.insert(keycode, *action);
+            }
+        }
+        Hotkeys { keys }
+    }
+
+    // Replaces every key of an action, taking them away from other actions
+    pub fn bind(&mut self, action: Action, keycodes: &[Keycode]) {
+        self.keys.retain(|_, &mut a| a != action);
+        for &keycode in keycodes {
+            self.keys.insert(keycode, action);
+        }
+    }
+
+    pub fn get(&self, keycode: Keycode) -> Option<Action> {
+        self.keys.get(&keycode).copied()
+    }
+
+    // Key names of an action, e.g. "F5" or "Backspace, F3"
+    pub fn describe(&self, action: Action) -> String {
+        let mut names: Vec<String> = self
+            .keys
+            .iter()
+            .filter(|(_, &a)| a == action)
+            .map(|(keycode, _)| keycode.name())
+            .collect();
+        names.sort();
+        names.join(", ")
+    }
+}
@@ -0,0 +1,55 @@
+use std::fs::File;
+use std::io::{BufWriter, Error, Result, Write};
+
+pub fn write_png(filename: &str, width: usize, height: usize, rgba: &[u8]) -> Result<()> {
+    assert!(
+        rgba.len() == width * height * 4,
+        "PNG data does not match {}x{} RGBA",
+        width,
+        height
+    );
+    let file = File::create(filename)?;
+    let mut encoder = png::Encoder::new(BufWriter::new(file), width as u32, height as u32);
+    encoder.set_color(png::ColorType::RGBA);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder
+        .write_header()
+        .map_err(Error::other)?;
+    writer
+        .write_image_data(rgba)
+        .map_err(Error::other)
+}
+
+// Binary (P4) PBM, one bool per pixel in row-major order, true is black
+pub fn write_pbm(filename: &str, width: usize, height: usize, pixels: &[bool]) -> Result<()> {
+    assert!(
+        pixels.len() == width * height,
+        "PBM data does not match {}x{}",
+        width,
+        height
+    );
+    let mut f = BufWriter::new(File::create(filename)?);
+    write!(f, "P4\n{} {}\n", width, height)?;
+    for row in pixels.chunks(width) {
+        let mut packed = vec![0u8; width.div_ceil(8)];
+        for (x, &pixel) in row.iter().enumerate() {
+            if pixel {
+                packed[x / 8] |= 0x80 >> (x % 8);
+            }
+        }
+        f.write_all(&packed)?;
+    }
+    f.flush()
+}
+
+// Nearest-neighbour upscale of an RGBA image
+pub fn scale_rgba(rgba: &[u8], width: usize, height: usize, scale: usize) -> Vec<u8> {
+    let mut scaled = Vec::with_capacity(rgba.len() * scale * scale);
+    for y in 0..height * scale {
+        let row = &rgba[(y / scale) * width * 4..(y / scale + 1) * width * 4];
+        for x in 0..width * scale {
+            scaled.extend_from_slice(&row[(x / scale) * 4..(x / scale) * 4 + 4]);
+        }
+    }
+    scaled
+}
@@ -0,0 +1,157 @@
+use super::cpu::cpu::CPU;
+use super::display::Display;
+use super::font;
+
+use sdl2::event::Event;
+use sdl2::mouse::MouseButton;
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+use std::collections::HashMap;
+
+// COSMAC VIP keypad layout
+const LAYOUT: [[usize; 4]; 4] = [
+    [0x1, 0x2, 0x3, 0xC],
+    [0x4, 0x5, 0x6, 0xD],
+    [0x7, 0x8, 0x9, 0xE],
+    [0xA, 0x0, 0xB, 0xF],
+];
+
+// SDL reports mouse events synthesized from touches with this device id
+const TOUCH_MOUSE_ID: u32 = u32::MAX;
+
+const PANEL_COLOR: Color = Color::RGBA(0, 0, 0, 160);
+const KEY_COLOR: Color = Color::RGBA(80, 80, 80, 200);
+const PRESSED_COLOR: Color = Color::RGBA(230, 160, 0, 230);
+const LABEL_COLOR: Color = Color::RGB(255, 255, 255);
+
+// Clickable hex keypad drawn over the bottom right corner of the window
+pub struct KeypadOverlay {
+    pub visible: bool,
+    mouse_key: Option<usize>,        // Key held by the left mouse button
+    finger_keys: HashMap<i64, usize>, // Keys held by touches, per finger
+}
+
+impl KeypadOverlay {
+    pub fn new(visible: bool) -> KeypadOverlay {
+        KeypadOverlay {
+            visible,
+            mouse_key: None,
+            finger_keys: HashMap::new(),
+        }
+    }
+
+    // Hiding the keypad releases whatever it was holding
+    pub fn toggle(&mut self, cpu: &mut CPU) {
+        self.visible = !self.visible;
+        if !self.visible {
+            if let Some(key) = self.mouse_key.take() {
+                cpu.release_key(key);
+            }
+            for (_, key) in self.finger_keys.drain() {
+                cpu.release_key(key);
+            }
+        }
+    }
+
+    // The panel takes two fifths of the shorter window side
+    fn panel(window_size: (u32, u32)) -> Rect {
+        let size = (window_size.0.min(window_size.1) * 2 / 5).max(4 * 12);
+        let margin = size / 20;
+        Rect::new(
+            window_size.0 as i32 - (size + margin) as i32,
+            window_size.1 as i32 - (size + margin) as i32,
+            size,
+            size,
+        )
+    }
+
+    fn key_rect(panel: Rect, row: usize, column: usize) -> Rect {
+        let cell = panel.width() / 4;
+        let gap = (cell / 10).max(1);
+        Rect::new(
+            panel.x() + (column as u32 * cell + gap) as i32,
+            panel.y() + (row as u32 * cell + gap) as i32,
+            cell - 2 * gap,
+            cell - 2 * gap,
+        )
+    }
+
+    fn key_at(window_size: (u32, u32), x: i32, y: i32) -> Option<usize> {
+        let panel = KeypadOverlay::panel(window_size);
+        for (row, keys) in LAYOUT.iter().enumerate() {
+            for (column, &key) in keys.iter().enumerate() {
+                if KeypadOverlay::key_rect(panel, row, column).contains_point((x, y)) {
+                    return Some(key);
+                }
+            }
+        }
+        None
+    }
+
+    pub fn draw(&self, display: &mut Display, key_status: &[bool; 0x10]) {
+        let panel = KeypadOverlay::panel(display.window_size());
+        display.fill_rect(panel, PANEL_COLOR);
+        for (row, keys) in LAYOUT.iter().enumerate() {
+            for (column, &key) in keys.iter().enumerate() {
+                let rect = KeypadOverlay::key_rect(panel, row, column);
+                let color = if key_status[key] { PRESSED_COLOR } else { KEY_COLOR };
+                display.fill_rect(rect, color);
+
+                let label = format!("{:X}", key);
+                let scale = (rect.height() / 2 / font::GLYPH_HEIGHT).max(1);
+                let (w, h) = (font::text_width(&label, scale) - scale, font::GLYPH_HEIGHT * scale);
+                let (x, y) = rect.center().into();
+                display.draw_text(x - w as i32 / 2, y - h as i32 / 2, scale, &label, LABEL_COLOR);
+            }
+        }
+    }
+
+    // Returns true when the event pressed or released a key of the overlay
+    pub fn handle_event(&mut self, event: &Event, cpu: &mut CPU, window_size: (u32, u32)) -> bool {
+        if !self.visible {
+            return false;
+        }
+        match *event {
+            Event::MouseButtonDown {
+                which,
+                mouse_btn: MouseButton::Left,
+                x,
+                y,
+                ..
+            } if which != TOUCH_MOUSE_ID => {
+                if let Some(key) = KeypadOverlay::key_at(window_size, x, y) {
+                    cpu.press_key(key);
+                    self.mouse_key = Some(key);
+                    return true;
+                }
+            }
+            Event::MouseButtonUp {
+                which,
+                mouse_btn: MouseButton::Left,
+                ..
+            } if which != TOUCH_MOUSE_ID => {
+                if let Some(key) = self.mouse_key.take() {
+                    cpu.release_key(key);
+                    return true;
+                }
+            }
+            // Touch coordinates are normalized to the window size
+            Event::FingerDown { finger_id, x, y, .. } => {
+                let (x, y) = (x * window_size.0 as f32, y * window_size.1 as f32);
+                if let Some(key) = KeypadOverlay::key_at(window_size, x as i32, y as i32) {
+                    cpu.press_key(key);
+                    self.finger_keys.insert(finger_id, key);
+                    return true;
+                }
+            }
+            Event::FingerUp { finger_id, .. } => {
+                if let Some(key) = self.finger_keys.remove(&finger_id) {
+                    cpu.release_key(key);
+                    return true;
+                }
+            }
+            _ => {}
+        }
+        false
+    }
+}
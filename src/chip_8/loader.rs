@@ -0,0 +1,265 @@
+use super::assembler;
+use super::browser;
+use super::cpu::cpu::CPU;
+use super::octo;
+use super::patch;
+
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+
+// A ROM is named by one of:
+//   game.ch8            a plain file
+//   -                   standard input
+//   game.hex, game.txt  a hex dump, see parse_hex
+//   pack.zip            the only ROM in the archive
+//   pack.zip#game.ch8   a ROM in the archive, by path or file name
+//   game.gif            an Octo cartridge
+pub enum LoadError {
+    Io(String, io::Error),
+    Archive(String, zip::result::ZipError),
+    NoRomInArchive(String),
+    SeveralRomsInArchive(String, Vec<String>),
+    MissingEntry(String, String),
+    Hex(String, usize, String), // File, line number and the token that is not a byte
+    Cartridge(String),
+    Empty(String),
+    TooLarge(String, usize, usize), // Name, size and space available
+    Patch(String, String),          // Patch file and what went wrong
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LoadError::Io(name, e) => write!(f, "unable to read {}: {}", name, e),
+            LoadError::Archive(name, e) => write!(f, "unable to read archive {}: {}", name, e),
+            LoadError::NoRomInArchive(name) => write!(
+                f,
+                "no {} file in {}",
+                browser::ROM_EXTENSIONS.join("/"),
+                name
+            ),
+            LoadError::SeveralRomsInArchive(name, entries) => write!(
+                f,
+                "{} holds several ROMs, pick one with {}#NAME: {}",
+                name,
+                name,
+                entries.join(", ")
+            ),
+            LoadError::MissingEntry(name, entry) => write!(f, "no {} in {}", entry, name),
+            LoadError::Hex(name, line, token) => {
+                write!(f, "{}:{}: `{}` is not a hex byte", name, line, token)
+            }
+            LoadError::Cartridge(message) => write!(f, "{}", message),
+            LoadError::Empty(name) => write!(f, "{} is empty", name),
+            LoadError::TooLarge(name, size, available) => write!(
+                f,
+                "{} is {} bytes but only {} fit in memory",
+                name, size, available
+            ),
+            LoadError::Patch(name, message) => write!(f, "unable to apply {}: {}", name, message),
+        }
+    }
+}
+
+const VIP_IMAGE_SIZE: usize = 0x200; // The VIP's CHIP-8 interpreter and its monitor ROM
+
+// Everything read_rom understands, judging by the name
+pub fn is_loadable(name: &str) -> bool {
+    name == "-"
+        || split_archive(name).is_some()
+        || browser::is_rom(Path::new(name))
+        || ["zip", "hex", "txt"].contains(&extension(name).as_str())
+}
+
+// The file behind a ROM name, None for standard input
+pub fn get_file(name: &str) -> Option<&str> {
+    match name {
+        "-" => None,
+        _ => Some(split_archive(name).map_or(name, |(archive, _)| archive)),
+    }
+}
+
+// "pack.zip#game.ch8" becomes ("pack.zip", "game.ch8")
+pub fn split_archive(name: &str) -> Option<(&str, &str)> {
+    // Searched in the bytes themselves since lowercasing can change the length of other characters
+    let end = name.as_bytes().windows(5).position(|w| w.eq_ignore_ascii_case(b".zip#"))? + ".zip".len();
+    Some((&name[..end], &name[end + 1..]))
+}
+
+// ROM paths inside an archive, leaving out cartridges which are only read from plain files
+pub fn list_archive(name: &str) -> Result<Vec<String>, LoadError> {
+    let file = File::open(name).map_err(|e| LoadError::Io(name.to_string(), e))?;
+    let archive = zip::ZipArchive::new(file).map_err(|e| LoadError::Archive(name.to_string(), e))?;
+    let mut roms: Vec<String> = archive
+        .file_names()
+        .filter(|path| browser::is_rom(Path::new(path)) && !octo::is_cartridge(path))
+        .map(|path| path.to_string())
+        .collect();
+    roms.sort();
+    Ok(roms)
+}
+
+// IPS or BPS patches named like the ROM and next to it, "game.ch8" or "pack.zip#game.ch8"
+// having "game.ips" and "game.bps"
+pub fn find_patches(name: &str) -> Vec<String> {
+    let file = match get_file(name) {
+        Some(file) => Path::new(file),
+        None => return vec![],
+    };
+    let rom = split_archive(name).map_or(file, |(_, entry)| Path::new(entry));
+    let stem = match rom.file_stem() {
+        Some(stem) => stem,
+        None => return vec![],
+    };
+    ["ips", "bps"]
+        .iter()
+        .map(|extension| file.with_file_name(format!("{}.{}", stem.to_string_lossy(), extension)))
+        .filter(|path| path.is_file())
+        .map(|path| path.to_string_lossy().into_owned())
+        .collect()
+}
+
+// Patches apply in order, each to the result of the previous one
+pub fn load_rom(name: &str, patches: &[String], cpu: &mut CPU) -> Result<(), LoadError> {
+    let mut rom = read_rom(name)?;
+    for patch_name in patches {
+        let data = read_file(patch_name)?;
+        rom = patch::apply(&rom, &data).map_err(|e| LoadError::Patch(patch_name.to_string(), e))?;
+    }
+    if rom.is_empty() {
+        return Err(LoadError::Empty(name.to_string()));
+    }
+    if rom.len() > cpu.max_rom_size() {
+        return Err(LoadError::TooLarge(name.to_string(), rom.len(), cpu.max_rom_size()));
+    }
+    cpu.load_rom(&rom);
+    Ok(())
+}
+
+pub fn read_vip_image(name: &str) -> Result<Vec<u8>, LoadError> {
+    let image = read_file(name)?;
+    if image.len() > VIP_IMAGE_SIZE {
+        return Err(LoadError::TooLarge(name.to_string(), image.len(), VIP_IMAGE_SIZE));
+    }
+    Ok(image)
+}
+
+pub fn read_rom(name: &str) -> Result<Vec<u8>, LoadError> {
+    if name == "-" {
+        let mut rom = vec![];
+        io::stdin()
+            .read_to_end(&mut rom)
+            .map_err(|e| LoadError::Io("standard input".to_string(), e))?;
+        return Ok(rom);
+    }
+    if let Some((archive, entry)) = split_archive(name) {
+        return read_archive(archive, Some(entry));
+    }
+    if octo::is_cartridge(name) {
+        return read_cartridge(name);
+    }
+    match extension(name).as_str() {
+        "zip" => read_archive(name, None),
+        "hex" | "txt" => {
+            let text = std::fs::read_to_string(name).map_err(|e| LoadError::Io(name.to_string(), e))?;
+            parse_hex(&text).map_err(|(line, token)| LoadError::Hex(name.to_string(), line, token))
+        }
+        _ => read_file(name),
+    }
+}
+
+fn extension(name: &str) -> String {
+    Path::new(name)
+        .extension()
+        .map(|extension| extension.to_string_lossy().to_lowercase())
+        .unwrap_or_default()
+}
+
+fn read_file(name: &str) -> Result<Vec<u8>, LoadError> {
+    let mut rom = vec![];
+    File::open(name)
+        .and_then(|mut file| file.read_to_end(&mut rom))
+        .map_err(|e| LoadError::Io(name.to_string(), e))?;
+    Ok(rom)
+}
+
+// Without an entry name the archive must hold exactly one ROM
+fn read_archive(name: &str, entry: Option<&str>) -> Result<Vec<u8>, LoadError> {
+    let roms = list_archive(name)?;
+
+    let path = match entry {
+        Some(entry) => roms
+            .iter()
+            .find(|path| *path == entry || Path::new(path).file_name().is_some_and(|file| file == entry))
+            .ok_or_else(|| LoadError::MissingEntry(name.to_string(), entry.to_string()))?,
+        None => match roms.len() {
+            0 => return Err(LoadError::NoRomInArchive(name.to_string())),
+            1 => &roms[0],
+            _ => return Err(LoadError::SeveralRomsInArchive(name.to_string(), roms)),
+        },
+    };
+
+    let file = File::open(name).map_err(|e| LoadError::Io(name.to_string(), e))?;
+    let mut archive = zip::ZipArchive::new(file).map_err(|e| LoadError::Archive(name.to_string(), e))?;
+    let mut rom = vec![];
+    archive
+        .by_name(path)
+        .map_err(|e| LoadError::Archive(name.to_string(), e))?
+        .read_to_end(&mut rom)
+        .map_err(|e| LoadError::Io(format!("{}#{}", name, path), e))?;
+    Ok(rom)
+}
+
+// Cartridges hold Octo source
+fn read_cartridge(name: &str) -> Result<Vec<u8>, LoadError> {
+    let cartridge = octo::read_cartridge(name).map_err(LoadError::Cartridge)?;
+    assembler::assemble(&cartridge.program).map_err(|e| LoadError::Cartridge(format!("{}: {}", name, e)))
+}
+
+// Bytes as hex digit pairs, e.g. "00E0 A22A", "0x00, 0xE0" or "0200: 00e0 a22a".
+// Tokens ending with ':' are addresses and are skipped, '#', ';' and "//" start a comment.
+// Returns the line number and token of the first error.
+pub fn parse_hex(text: &str) -> Result<Vec<u8>, (usize, String)> {
+    let mut bytes = vec![];
+    for (number, line) in text.lines().enumerate() {
+        let line = ["#", ";", "//"]
+            .iter()
+            .filter_map(|comment| line.find(comment))
+            .min()
+            .map_or(line, |end| &line[..end]);
+        for token in line.split(|c: char| c.is_whitespace() || c == ',') {
+            if token.is_empty() || token.ends_with(':') {
+                continue;
+            }
+            let digits = token
+                .strip_prefix("0x")
+                .or_else(|| token.strip_prefix("0X"))
+                .unwrap_or(token);
+            let valid = !digits.is_empty()
+                && digits.len() % 2 == 0
+                && digits.chars().all(|c| c.is_ascii_hexdigit());
+            if !valid {
+                return Err((number + 1, token.to_string()));
+            }
+            for pair in (0..digits.len()).step_by(2) {
+                bytes.push(u8::from_str_radix(&digits[pair..pair + 2], 16).unwrap());
+            }
+        }
+    }
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_archive_names() {
+        assert_eq!(split_archive("games.zip#pong.ch8"), Some(("games.zip", "pong.ch8")));
+        assert_eq!(split_archive("Games.ZIP#Pong.ch8"), Some(("Games.ZIP", "Pong.ch8")));
+        assert_eq!(split_archive("İstanbul.zip#pong.ch8"), Some(("İstanbul.zip", "pong.ch8")));
+        assert_eq!(split_archive("pong.ch8"), None);
+    }
+}
@@ -0,0 +1,117 @@
+use super::display::Display;
+use super::font;
+use super::hotkeys::{Action, Hotkeys};
+
+use sdl2::keyboard::Keycode;
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+
+// Label, action for Enter or Right, action for Left
+pub const ITEMS: [(&str, Action, Action); 18] = [
+    ("Resume", Action::Menu, Action::Menu),
+    ("Open ROM", Action::OpenRom, Action::OpenRom),
+    ("Speed", Action::SpeedUp, Action::SpeedDown),
+    ("Palette", Action::Palette, Action::Palette),
+    ("Filter", Action::Filter, Action::Filter),
+    ("Persistence", Action::Persistence, Action::Persistence),
+    ("Integer scaling", Action::IntegerScaling, Action::IntegerScaling),
+    ("Fullscreen", Action::Fullscreen, Action::Fullscreen),
+    ("Keypad", Action::Keypad, Action::Keypad),
+    ("Status line", Action::StatusLine, Action::StatusLine),
+    ("Sound", Action::Mute, Action::Mute),
+    ("State slot", Action::NextSlot, Action::NextSlot),
+    ("Save state", Action::SaveState, Action::SaveState),
+    ("Load state", Action::LoadState, Action::LoadState),
+    ("Screenshot", Action::Screenshot, Action::Screenshot),
+    ("Rebind keys", Action::RebindKeys, Action::RebindKeys),
+    ("Reset", Action::Reset, Action::Reset),
+    ("Quit", Action::Quit, Action::Quit),
+];
+
+const LABEL_COLUMNS: usize = 17;
+const VALUE_COLUMNS: usize = 12;
+
+const BACKGROUND_COLOR: Color = Color::RGBA(0, 0, 0, 200);
+const SELECTED_COLOR: Color = Color::RGBA(230, 160, 0, 230);
+const TEXT_COLOR: Color = Color::RGB(255, 255, 255);
+const HOTKEY_COLOR: Color = Color::RGB(150, 150, 150);
+
+// Pause menu listing the emulator actions, the emulation is stopped while it is open
+pub struct Menu {
+    pub open: bool,
+    selected: usize,
+}
+
+impl Menu {
+    pub fn new() -> Menu {
+        Menu {
+            open: false,
+            selected: 0,
+        }
+    }
+
+    pub fn toggle(&mut self) {
+        self.open = !self.open;
+        self.selected = 0;
+    }
+
+    // Returns the action chosen with a key press, if any
+    pub fn handle_key(&mut self, keycode: Keycode) -> Option<Action> {
+        match keycode {
+            Keycode::Up => self.selected = (self.selected + ITEMS.len() - 1) % ITEMS.len(),
+            Keycode::Down => self.selected = (self.selected + 1) % ITEMS.len(),
+            Keycode::Return | Keycode::KpEnter | Keycode::Right => return Some(ITEMS[self.selected].1),
+            Keycode::Left => return Some(ITEMS[self.selected].2),
+            Keycode::Escape => return Some(Action::Menu),
+            _ => {}
+        }
+        None
+    }
+
+    // `values` holds the current setting of every item, in the order of ITEMS
+    pub fn draw(&self, display: &mut Display, hotkeys: &Hotkeys, values: &[Option<String>]) {
+        let lines: Vec<(String, String)> = ITEMS
+            .iter()
+            .zip(values.iter())
+            .map(|((label, action, left), value)| {
+                let setting = format!("{:<width$}{}", label, value.as_deref().unwrap_or(""), width = LABEL_COLUMNS);
+                let mut keys = hotkeys.describe(*action);
+                if left != action {
+                    keys = format!("{} / {}", hotkeys.describe(*left), keys);
+                }
+                (setting, keys)
+            })
+            .collect();
+
+        // Largest font scale that fits every line, with a margin of one glyph around the text
+        let (window_width, window_height) = display.window_size();
+        let columns = LABEL_COLUMNS + VALUE_COLUMNS + lines.iter().map(|(_, keys)| keys.len()).max().unwrap_or(0);
+        let rows = ITEMS.len() as u32 + 4; // Title, blank line and margins
+        let scale = (window_width / ((columns as u32 + 2) * font::GLYPH_WIDTH))
+            .min(window_height * 2 / (rows * font::GLYPH_HEIGHT * 3))
+            .max(1);
+        let line_height = (font::GLYPH_HEIGHT * scale * 3 / 2) as i32;
+        let column_width = (font::GLYPH_WIDTH * scale) as i32;
+
+        display.fill_rect(Rect::new(0, 0, window_width, window_height), BACKGROUND_COLOR);
+        let left = (window_width as i32 - columns as i32 * column_width) / 2;
+        let mut y = (window_height as i32 - rows as i32 * line_height) / 2 + line_height;
+        display.draw_text(left, y, scale, "PAUSED", TEXT_COLOR);
+        y += 2 * line_height;
+        for (i, (setting, keys)) in lines.iter().enumerate() {
+            if i == self.selected {
+                let bar = Rect::new(
+                    left - column_width / 2,
+                    y - line_height / 6,
+                    (columns as i32 * column_width + column_width) as u32,
+                    line_height as u32,
+                );
+                display.fill_rect(bar, SELECTED_COLOR);
+            }
+            display.draw_text(left, y, scale, setting, TEXT_COLOR);
+            let keys_x = left + (LABEL_COLUMNS + VALUE_COLUMNS) as i32 * column_width;
+            display.draw_text(keys_x, y, scale, keys, HOTKEY_COLOR);
+            y += line_height;
+        }
+    }
+}
@@ -0,0 +1,99 @@
+use super::cpu::fonts::SystemFont;
+use super::palette::Palette;
+
+use serde_json::Value;
+use std::fs::File;
+use std::path::Path;
+
+// Octo cartridges are GIFs whose palette indices carry the payload in their two low bits,
+// four pixels per byte with the first pixel in the high bits, over every frame in order.
+// The payload is a big endian 32 bit length followed by JSON: {"options": {...}, "program": "..."}
+pub struct Cartridge {
+    pub program: String,
+    pub options: Value,
+}
+
+pub fn is_cartridge(filename: &str) -> bool {
+    Path::new(filename)
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("gif"))
+}
+
+pub fn read_cartridge(filename: &str) -> Result<Cartridge, String> {
+    let file = File::open(filename).map_err(|e| format!("unable to read {}: {}", filename, e))?;
+    let mut options = gif::DecodeOptions::new();
+    options.set_color_output(gif::ColorOutput::Indexed);
+    let mut decoder = options
+        .read_info(file)
+        .map_err(|e| format!("{} is not a GIF: {}", filename, e))?;
+
+    let mut payload = vec![];
+    let mut pending = 0u8; // Bits of the byte being assembled
+    let mut pending_pixels = 0;
+    while let Some(frame) = decoder
+        .read_next_frame()
+        .map_err(|e| format!("unable to decode {}: {}", filename, e))?
+    {
+        for &index in frame.buffer.iter() {
+            pending = (pending << 2) | (index & 3);
+            pending_pixels += 1;
+            if pending_pixels == 4 {
+                payload.push(pending);
+                pending_pixels = 0;
+            }
+        }
+    }
+
+    let not_a_cartridge = || format!("{} is not an Octo cartridge", filename);
+    if payload.len() < 4 {
+        return Err(not_a_cartridge());
+    }
+    let length = u32::from_be_bytes([payload[0], payload[1], payload[2], payload[3]]) as usize;
+    let json = payload.get(4..4 + length).ok_or_else(not_a_cartridge)?;
+    // Octo writes one byte per character, which is only UTF-8 for ASCII text
+    let json = match std::str::from_utf8(json) {
+        Ok(json) => json.to_string(),
+        Err(_) => json.iter().map(|&b| b as char).collect(),
+    };
+    let mut value: Value = serde_json::from_str(&json).map_err(|_| not_a_cartridge())?;
+    let program = value["program"].as_str().ok_or_else(not_a_cartridge)?.to_string();
+    Ok(Cartridge {
+        program,
+        options: value["options"].take(),
+    })
+}
+
+// Octo's tickrate is in instructions per frame
+pub fn get_clock_hz(options: &Value) -> Option<f64> {
+    options["tickrate"].as_f64().map(|tickrate| tickrate * 60.)
+}
+
+// Octo's quirk flags by the names Quirks::set takes, for the flags the cartridge has
+pub fn get_quirks(options: &Value) -> Vec<(&'static str, bool)> {
+    [
+        ("shiftQuirks", "shift"),
+        ("loadStoreQuirks", "load-store"),
+        ("jumpQuirks", "jump"),
+        ("logicQuirks", "logic"),
+        ("clipQuirks", "clip"),
+        ("vBlankQuirks", "vblank"),
+        ("vfOrderQuirks", "vf-order"),
+    ]
+    .iter()
+    .filter_map(|&(key, name)| options[key].as_bool().map(|on| (name, on)))
+    .collect()
+}
+
+// Octo's "fish" font has no counterpart here
+pub fn get_font(options: &Value) -> Option<SystemFont> {
+    options["fontStyle"].as_str().and_then(SystemFont::from_name)
+}
+
+// Octo has no colors for the planes beyond the blend color, so they map directly to ours
+pub fn get_palette(options: &Value) -> Option<Palette> {
+    let colors: Option<Vec<&str>> = ["backgroundColor", "fillColor", "fillColor2", "blendColor"]
+        .iter()
+        .map(|key| options[*key].as_str())
+        .collect();
+    Palette::from_hex_list("cartridge", &colors?.join(",")).ok()
+}
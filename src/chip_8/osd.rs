@@ -0,0 +1,79 @@
+use super::display::Display;
+use super::font;
+
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+use std::time::{Duration, Instant};
+
+const MESSAGE_DURATION: Duration = Duration::from_secs(2);
+const MAX_MESSAGES: usize = 4;
+
+const BACKGROUND_COLOR: Color = Color::RGBA(0, 0, 0, 160);
+const TEXT_COLOR: Color = Color::RGB(255, 255, 255);
+
+// Transient messages at the bottom left of the window and an optional status line at the top
+pub struct Osd {
+    messages: Vec<(String, Instant)>, // Text and time it was shown
+    pub status_line: bool,
+    frames: u32,          // Emulated frames since fps_since
+    fps_since: Instant,
+    fps: f64,
+}
+
+impl Osd {
+    pub fn new(status_line: bool) -> Osd {
+        Osd {
+            messages: vec![],
+            status_line,
+            frames: 0,
+            fps_since: Instant::now(),
+            fps: 0.,
+        }
+    }
+
+    pub fn show(&mut self, message: String) {
+        self.messages.push((message, Instant::now()));
+        if self.messages.len() > MAX_MESSAGES {
+            self.messages.remove(0);
+        }
+    }
+
+    // Called for every emulated frame, the rate is averaged over one second
+    pub fn count_frame(&mut self) {
+        self.frames += 1;
+        let elapsed = self.fps_since.elapsed();
+        if elapsed >= Duration::from_secs(1) {
+            self.fps = self.frames as f64 / elapsed.as_secs_f64();
+            self.frames = 0;
+            self.fps_since = Instant::now();
+        }
+    }
+
+    pub fn get_fps(&self) -> f64 {
+        self.fps
+    }
+
+    // `status` is only drawn when the status line is enabled
+    pub fn draw(&mut self, display: &mut Display, status: &str) {
+        self.messages.retain(|(_, shown)| shown.elapsed() < MESSAGE_DURATION);
+
+        let (_, window_height) = display.window_size();
+        let scale = (window_height / 160).clamp(1, 4);
+        let line_height = (font::GLYPH_HEIGHT + 2) * scale;
+        if self.status_line {
+            draw_line(display, 0, scale, status);
+        }
+        let mut y = window_height as i32 - (self.messages.len() as u32 * line_height) as i32;
+        for (message, _) in self.messages.iter() {
+            draw_line(display, y, scale, message);
+            y += line_height as i32;
+        }
+    }
+}
+
+// Text over a dark band so it stays readable on any palette
+fn draw_line(display: &mut Display, y: i32, scale: u32, text: &str) {
+    let width = font::text_width(text, scale) + 2 * scale;
+    display.fill_rect(Rect::new(0, y, width, (font::GLYPH_HEIGHT + 2) * scale), BACKGROUND_COLOR);
+    display.draw_text(scale as i32, y + scale as i32, scale, text, TEXT_COLOR);
+}
@@ -0,0 +1,50 @@
+use sdl2::pixels::Color;
+
+// Colors are indexed by plane bits: 0 background, 1 first plane, 2 second plane, 3 both
+#[derive(Clone)]
+pub struct Palette {
+    pub name: String,
+    pub colors: [Color; 4],
+}
+
+impl Palette {
+    pub fn new(name: &str, colors: [u32; 4]) -> Palette {
+        let to_color = |rgb: u32| Color::RGB((rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8);
+        Palette {
+            name: name.to_string(),
+            colors: [
+                to_color(colors[0]),
+                to_color(colors[1]),
+                to_color(colors[2]),
+                to_color(colors[3]),
+            ],
+        }
+    }
+
+    pub fn builtin() -> Vec<Palette> {
+        vec![
+            Palette::new("classic", [0x000000, 0xFFFFFF, 0xAAAAAA, 0x555555]),
+            Palette::new("lcd", [0x9BBC0F, 0x0F380F, 0x306230, 0x8BAC0F]),
+            Palette::new("amber", [0x1A0F00, 0xFFB000, 0xB36B00, 0xFFD37A]),
+            Palette::new("octo", [0x996600, 0xFFCC00, 0xFF6600, 0x662200]),
+        ]
+    }
+
+    // Two to four comma separated hex colors, e.g. "000000,FFFFFF" or "#112233,#445566"
+    pub fn from_hex_list(name: &str, list: &str) -> Result<Palette, String> {
+        let mut colors = vec![];
+        for hex in list.split(',') {
+            let hex = hex.trim().trim_start_matches('#');
+            if hex.len() != 6 {
+                return Err(format!("invalid color: {}", hex));
+            }
+            colors.push(u32::from_str_radix(hex, 16).map_err(|_| format!("invalid color: {}", hex))?);
+        }
+        match colors.len() {
+            2 => Ok(Palette::new(name, [colors[0], colors[1], colors[1], colors[1]])),
+            3 => Ok(Palette::new(name, [colors[0], colors[1], colors[2], colors[1]])),
+            4 => Ok(Palette::new(name, [colors[0], colors[1], colors[2], colors[3]])),
+            _ => Err(format!("expected 2 to 4 colors, got {}", colors.len())),
+        }
+    }
+}
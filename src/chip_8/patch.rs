@@ -0,0 +1,275 @@
+use std::convert::TryFrom;
+
+const MAX_TARGET_SIZE: usize = 0x1000; // The largest RAM, bigger ROMs are refused when loading
+
+// IPS and BPS patches, told apart by their header
+pub fn apply(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, String> {
+    if patch.starts_with(b"PATCH") {
+        apply_ips(rom, &patch[5..])
+    } else if patch.starts_with(b"BPS1") {
+        apply_bps(rom, patch)
+    } else {
+        Err("not an IPS or BPS patch".to_string())
+    }
+}
+
+// Records of a 24 bit offset and a 16 bit size followed by the data, a size of 0 being a run
+// of one byte, until "EOF" and an optional 24 bit size to truncate the result to
+fn apply_ips(rom: &[u8], records: &[u8]) -> Result<Vec<u8>, String> {
+    let truncated = || "truncated IPS patch".to_string();
+    let read = |at: usize, size: usize| -> Result<usize, String> {
+        let bytes = records.get(at..at + size).ok_or_else(truncated)?;
+        Ok(bytes.iter().fold(0, |value, &b| value << 8 | b as usize))
+    };
+
+    let mut target = rom.to_vec();
+    let mut at = 0;
+    loop {
+        if records.get(at..at + 3) == Some(b"EOF") {
+            if records.len() >= at + 6 {
+                target.truncate(read(at + 3, 3)?);
+            }
+            return Ok(target);
+        }
+        let offset = read(at, 3)?;
+        let size = read(at + 3, 2)?;
+        at += 5;
+        let data = if size == 0 {
+            let run = read(at, 2)?;
+            let value = read(at + 2, 1)? as u8;
+            at += 3;
+            vec![value; run]
+        } else {
+            let data = records.get(at..at + size).ok_or_else(truncated)?.to_vec();
+            at += size;
+            data
+        };
+        if offset + data.len() > MAX_TARGET_SIZE {
+            return Err("the IPS patch writes past the end of the largest ROM".to_string());
+        }
+        if target.len() < offset + data.len() {
+            target.resize(offset + data.len(), 0);
+        }
+        target[offset..offset + data.len()].copy_from_slice(&data);
+    }
+}
+
+// Header, source, target and metadata sizes, actions, then the CRC32 of the source,
+// the target and the patch itself
+fn apply_bps(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, String> {
+    let truncated = || "truncated BPS patch".to_string();
+    if patch.len() < 4 + 12 {
+        return Err(truncated());
+    }
+    let footer = patch.len() - 12;
+    let read_crc = |at: usize| u32::from_le_bytes([patch[at], patch[at + 1], patch[at + 2], patch[at + 3]]);
+    if crc32(&patch[..patch.len() - 4]) != read_crc(footer + 8) {
+        return Err("corrupt BPS patch, its checksum does not match".to_string());
+    }
+    if crc32(rom) != read_crc(footer) {
+        return Err("the BPS patch is for a different ROM, the checksum does not match".to_string());
+    }
+
+    let actions = &patch[..footer];
+    let mut at = 4;
+    let source_size = read_number(actions, &mut at)?;
+    let target_size = read_number(actions, &mut at)?;
+    let metadata_size = read_number(actions, &mut at)?;
+    if source_size != rom.len() {
+        return Err("the BPS patch is for a ROM of a different size".to_string());
+    }
+    at = at.saturating_add(metadata_size);
+
+    let mut target = Vec::with_capacity(target_size.min(MAX_TARGET_SIZE));
+    let (mut source_offset, mut target_offset) = (0isize, 0isize);
+    while at < footer {
+        let action = read_number(actions, &mut at)?;
+        let length = (action >> 2) + 1;
+        if length > target_size - target.len().min(target_size) {
+            return Err("the BPS patch writes past the end of the ROM it makes".to_string());
+        }
+        match action & 3 {
+            0 => {
+                // Source read, the same bytes as in the ROM at the output position
+                let start = target.len();
+                target.extend_from_slice(rom.get(start..start + length).ok_or_else(truncated)?);
+            }
+            1 => {
+                let data = actions.get(at..at + length).ok_or_else(truncated)?;
+                target.extend_from_slice(data);
+                at += length;
+            }
+            2 => {
+                source_offset = source_offset.checked_add(read_offset(actions, &mut at)?).ok_or_else(truncated)?;
+                let data = usize::try_from(source_offset)
+                    .ok()
+                    .and_then(|start| rom.get(start..start.checked_add(length)?))
+                    .ok_or_else(truncated)?;
+                target.extend_from_slice(data);
+                source_offset += length as isize;
+            }
+            _ => {
+                // Target copy, byte by byte since the ranges may overlap
+                target_offset = target_offset.checked_add(read_offset(actions, &mut at)?).ok_or_else(truncated)?;
+                for _ in 0..length {
+                    let byte = *target.get(target_offset as usize).filter(|_| target_offset >= 0).ok_or_else(truncated)?;
+                    target.push(byte);
+                    target_offset += 1;
+                }
+            }
+        }
+    }
+
+    if target.len() != target_size || crc32(&target) != read_crc(footer + 4) {
+        return Err("the BPS patch produced a different ROM than expected".to_string());
+    }
+    Ok(target)
+}
+
+// Variable length number, 7 bits per byte with the last byte flagged by the high bit
+fn read_number(data: &[u8], at: &mut usize) -> Result<usize, String> {
+    let too_large = || "corrupt BPS patch, a number is too large".to_string();
+    let mut value = 0usize;
+    let mut shift = 1usize;
+    loop {
+        let byte = *data.get(*at).ok_or_else(|| "truncated BPS patch".to_string())?;
+        *at += 1;
+        value = ((byte & 0x7F) as usize)
+            .checked_mul(shift)
+            .and_then(|digit| value.checked_add(digit))
+            .ok_or_else(too_large)?;
+        if byte & 0x80 != 0 {
+            return Ok(value);
+        }
+        shift = shift.checked_mul(0x80).ok_or_else(too_large)?;
+        value = value.checked_add(shift).ok_or_else(too_large)?;
+    }
+}
+
+// Relative offsets are stored as magnitude and sign bit
+fn read_offset(data: &[u8], at: &mut usize) -> Result<isize, String> {
+    let value = read_number(data, at)?;
+    let magnitude = (value >> 1) as isize;
+    Ok(if value & 1 != 0 { -magnitude } else { magnitude })
+}
+
+// CRC-32 as used by BPS, ZIP and PNG
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A BPS patch of the given actions with a valid footer
+    fn bps(source: &[u8], target: &[u8], actions: &[u8]) -> Vec<u8> {
+        let mut patch = b"BPS1".to_vec();
+        patch.push(0x80 | source.len() as u8);
+        patch.push(0x80 | target.len() as u8);
+        patch.push(0x80); // No metadata
+        patch.extend_from_slice(actions);
+        patch.extend_from_slice(&crc32(source).to_le_bytes());
+        patch.extend_from_slice(&crc32(target).to_le_bytes());
+        let crc = crc32(&patch);
+        patch.extend_from_slice(&crc.to_le_bytes());
+        patch
+    }
+
+    #[test]
+    fn ips_records_and_runs() {
+        let mut patch = b"PATCH".to_vec();
+        patch.extend_from_slice(&[0x00, 0x00, 0x01, 0x00, 0x02, 0xAA, 0xBB]); // 2 bytes at 1
+        patch.extend_from_slice(&[0x00, 0x00, 0x05, 0x00, 0x00, 0x00, 0x03, 0xCC]); // Run of 3 at 5
+        patch.extend_from_slice(b"EOF");
+        assert_eq!(apply(&[0; 4], &patch), Ok(vec![0x00, 0xAA, 0xBB, 0x00, 0x00, 0xCC, 0xCC, 0xCC]));
+    }
+
+    #[test]
+    fn ips_truncates_after_eof() {
+        let mut patch = b"PATCH".to_vec();
+        patch.extend_from_slice(&[0x00, 0x00, 0x00, 0x00, 0x01, 0x11]);
+        patch.extend_from_slice(b"EOF");
+        patch.extend_from_slice(&[0x00, 0x00, 0x02]);
+        assert_eq!(apply(&[1, 2, 3, 4], &patch), Ok(vec![0x11, 2]));
+    }
+
+    #[test]
+    fn ips_without_eof_is_truncated() {
+        let mut patch = b"PATCH".to_vec();
+        patch.extend_from_slice(&[0x00, 0x00, 0x00, 0x00, 0x01, 0x11]);
+        assert_eq!(apply(&[1, 2], &patch), Err("truncated IPS patch".to_string()));
+    }
+
+    #[test]
+    fn ips_past_the_largest_rom() {
+        let mut patch = b"PATCH".to_vec();
+        patch.extend_from_slice(&[0x00, 0x0F, 0xFF, 0x00, 0x01, 0x11]); // Fills the last byte
+        patch.extend_from_slice(b"EOF");
+        assert_eq!(apply(&[], &patch).map(|rom| rom.len()), Ok(MAX_TARGET_SIZE));
+
+        let mut patch = b"PATCH".to_vec();
+        patch.extend_from_slice(&[0xFF, 0xFF, 0xFF, 0x00, 0x00, 0xFF, 0xFF, 0x00]); // Run far past it
+        patch.extend_from_slice(b"EOF");
+        assert_eq!(
+            apply(&[1, 2], &patch),
+            Err("the IPS patch writes past the end of the largest ROM".to_string())
+        );
+    }
+
+    #[test]
+    fn bps_actions() {
+        let source = [1, 2, 3, 4];
+        let target = [1, 2, 9, 9, 9, 3, 4];
+        let actions = [
+            0x84, // Source read of 2
+            0x81, 0x09, // Target read of 1
+            0x8F, 0x84, // Target copy of 4 from 2, repeating the byte it just wrote
+        ];
+        let patch = bps(&source, &[1, 2, 9, 9, 9, 9, 9], &actions);
+        assert_eq!(apply(&source, &patch), Ok(vec![1, 2, 9, 9, 9, 9, 9]));
+
+        let actions = [
+            0x84, // Source read of 2
+            0x89, 0x09, 0x09, 0x09, // Target read of 3
+            0x86, 0x84, // Source copy of 2 from offset 2
+        ];
+        assert_eq!(apply(&source, &bps(&source, &target, &actions)), Ok(target.to_vec()));
+    }
+
+    #[test]
+    fn bps_checksums() {
+        let source = [1, 2, 3, 4];
+        let target = [1, 2, 3, 5];
+        let actions = [0x88, 0x81, 0x05]; // Source read of 3, target read of 1
+        let patch = bps(&source, &target, &actions);
+        assert_eq!(apply(&source, &patch), Ok(target.to_vec()));
+
+        let error = apply(&[1, 2, 3, 6], &patch).unwrap_err();
+        assert!(error.contains("different ROM"), "{}", error);
+
+        let mut corrupt = patch.clone();
+        corrupt[7] ^= 1;
+        let error = apply(&source, &corrupt).unwrap_err();
+        assert!(error.starts_with("corrupt"), "{}", error);
+
+        let wrong_target = bps(&source, &[1, 2, 3, 6], &actions);
+        let error = apply(&source, &wrong_target).unwrap_err();
+        assert!(error.contains("produced a different ROM"), "{}", error);
+    }
+
+    #[test]
+    fn bps_number_overflow() {
+        let mut at = 0;
+        assert!(read_number(&[0x7F; 16], &mut at).is_err());
+        let mut at = 0;
+        assert_eq!(read_number(&[0x00, 0x80], &mut at), Ok(0x80));
+    }
+}
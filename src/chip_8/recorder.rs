@@ -0,0 +1,196 @@
+use super::audio::{Beeper, BufferBeeper, Tone, SAMPLE_RATE};
+use super::cpu::cpu::{CPU, FRAMES_PER_SECOND};
+use super::display::{HEIGHT, WIDTH};
+use super::image;
+use super::wav;
+
+use std::borrow::Cow;
+use std::fs::File;
+use std::io::{BufWriter, Error, Result, Write};
+
+const GIF_MIN_DELAY: u64 = 2; // Most viewers treat shorter delays as 10/100 s
+
+enum Output {
+    Gif {
+        encoder: gif::Encoder<BufWriter<File>>,
+        pending: Option<(Vec<u8>, u64)>, // Scaled RGBA and the frame it was first shown
+    },
+    Y4m {
+        file: BufWriter<File>,
+        wav_filename: String,
+        beeper: BufferBeeper,
+    },
+}
+
+// Captures one frame per emulated 60 Hz tick
+pub struct Recorder {
+    pub filename: String,
+    scale: usize,
+    frame: u64,
+    output: Output,
+}
+
+impl Recorder {
+    // The format follows the extension: .y4m (plus a .wav next to it), anything else is a GIF
+    pub fn start(filename: &str, scale: u32, tone: Tone) -> Result<Recorder> {
+        let scale = scale.max(1) as usize;
+        let (width, height) = (WIDTH * scale, HEIGHT * scale);
+        let file = BufWriter::new(File::create(filename)?);
+        let output = if filename.to_lowercase().ends_with(".y4m") {
+            let mut file = file;
+            writeln!(
+                file,
+                "YUV4MPEG2 W{} H{} F{}:1 Ip A1:1 C444",
+                width, height, FRAMES_PER_SECOND
+            )?;
+            Output::Y4m {
+                file,
+                wav_filename: format!("{}.wav", &filename[..filename.len() - 4]),
+                beeper: BufferBeeper::new(tone, FRAMES_PER_SECOND),
+            }
+        } else {
+            let mut encoder = gif::Encoder::new(file, width as u16, height as u16, &[])
+                .map_err(Error::other)?;
+            encoder
+                .set_repeat(gif::Repeat::Infinite)
+                .map_err(Error::other)?;
+            Output::Gif {
+                encoder,
+                pending: None,
+            }
+        };
+
+        Ok(Recorder {
+            filename: filename.to_string(),
+            scale,
+            frame: 0,
+            output,
+        })
+    }
+
+    pub fn capture_frame(&mut self, cpu: &CPU) -> Result<()> {
+        let rgba = image::scale_rgba(cpu.get_frame_buffer(), WIDTH, HEIGHT, self.scale);
+        let frame = self.frame;
+        self.frame += 1;
+
+        match &mut self.output {
+            Output::Gif { encoder, pending } => {
+                // Identical frames extend the previous one, frames too short for GIF are dropped
+                *pending = match pending.take() {
+                    None => Some((rgba, frame)),
+                    Some((last, start)) if last == rgba => Some((last, start)),
+                    Some((_, start)) if centiseconds(frame) - centiseconds(start) < GIF_MIN_DELAY => {
+                        Some((rgba, start))
+                    }
+                    Some((last, start)) => {
+                        let delay = centiseconds(frame) - centiseconds(start);
+                        write_gif_frame(encoder, &last, self.scale, delay)?;
+                        Some((rgba, frame))
+                    }
+                };
+            }
+            Output::Y4m { file, beeper, .. } => {
+                file.write_all(b"FRAME\n")?;
+                let yuv: Vec<[u8; 3]> = rgba.chunks(4).map(|p| rgb_to_yuv(p[0], p[1], p[2])).collect();
+                for plane in 0..3 {
+                    let bytes: Vec<u8> = yuv.iter().map(|p| p[plane]).collect();
+                    file.write_all(&bytes)?;
+                }
+                beeper.set_playing(cpu.is_sound_playing());
+                beeper.render_frame();
+            }
+        }
+        Ok(())
+    }
+
+    // Starts recording, reporting failures on the console
+    pub fn start_or_report(filename: &str, scale: u32, tone: Tone) -> Option<Recorder> {
+        match Recorder::start(filename, scale, tone) {
+            Ok(recorder) => {
+                println!("Recording to {}", filename);
+                Some(recorder)
+            }
+            Err(e) => {
+                println!("Unable to record to {}: {}", filename, e);
+                None
+            }
+        }
+    }
+
+    pub fn finish(self) {
+        let filename = self.filename.clone();
+        match self.stop() {
+            Ok(()) => println!("Recording written to {}", filename),
+            Err(e) => println!("Unable to finish {}: {}", filename, e),
+        }
+    }
+
+    pub fn stop(self) -> Result<()> {
+        match self.output {
+            Output::Gif {
+                mut encoder,
+                pending,
+            } => {
+                if let Some((last, start)) = pending {
+                    let delay = (centiseconds(self.frame) - centiseconds(start)).max(GIF_MIN_DELAY);
+                    write_gif_frame(&mut encoder, &last, self.scale, delay)?;
+                }
+                encoder.into_inner()?.flush() // Writes the trailer
+            }
+            Output::Y4m {
+                mut file,
+                wav_filename,
+                beeper,
+            } => {
+                file.flush()?;
+                wav::write_wav(&wav_filename, SAMPLE_RATE as u32, &beeper.samples)
+            }
+        }
+    }
+}
+
+fn centiseconds(frame: u64) -> u64 {
+    (frame * 100 + FRAMES_PER_SECOND as u64 / 2) / FRAMES_PER_SECOND as u64
+}
+
+fn write_gif_frame(
+    encoder: &mut gif::Encoder<BufWriter<File>>,
+    rgba: &[u8],
+    scale: usize,
+    delay: u64,
+) -> Result<()> {
+    // Frames only hold palette colors and their blends, so a local palette is enough
+    let mut palette: Vec<[u8; 3]> = vec![];
+    let mut indices = Vec::with_capacity(rgba.len() / 4);
+    for pixel in rgba.chunks(4) {
+        let color = [pixel[0], pixel[1], pixel[2]];
+        let index = match palette.iter().position(|&c| c == color) {
+            Some(index) => index,
+            None if palette.len() < 256 => {
+                palette.push(color);
+                palette.len() - 1
+            }
+            None => 0,
+        };
+        indices.push(index as u8);
+    }
+
+    let frame = gif::Frame {
+        width: (WIDTH * scale) as u16,
+        height: (HEIGHT * scale) as u16,
+        delay: delay.min(u16::MAX as u64) as u16,
+        palette: Some(palette.concat()),
+        buffer: Cow::Owned(indices),
+        ..gif::Frame::default()
+    };
+    encoder.write_frame(&frame).map_err(Error::other)
+}
+
+// BT.601 full range
+fn rgb_to_yuv(r: u8, g: u8, b: u8) -> [u8; 3] {
+    let (r, g, b) = (r as f32, g as f32, b as f32);
+    let y = 0.299 * r + 0.587 * g + 0.114 * b;
+    let u = 128. - 0.168736 * r - 0.331264 * g + 0.5 * b;
+    let v = 128. + 0.5 * r - 0.418688 * g - 0.081312 * b;
+    [y.round() as u8, u.round() as u8, v.round() as u8]
+}
@@ -0,0 +1,101 @@
+use std::io::Result;
+use std::path::PathBuf;
+
+// The HP48's RPL user flags, where SUPER-CHIP's FX75 and FX85 keep up to 8 registers and
+// XO-CHIP up to 16. Like on the calculator they survive switching off: each ROM's flags are
+// kept in a file named after it in the user data directory.
+pub const SIZE: usize = 16;
+
+const EXTENSION: &str = ".flags";
+
+#[derive(Clone)]
+pub struct RplFlags {
+    flags: [u8; SIZE],
+    file: Option<PathBuf>, // Kept in memory only without one
+}
+
+impl RplFlags {
+    pub fn new() -> RplFlags {
+        RplFlags {
+            flags: [0; SIZE],
+            file: None,
+        }
+    }
+
+    // The flags the ROM with this file name saved last, all clear when it never did
+    pub fn open(rom_name: &str) -> RplFlags {
+        let file = flags_file(rom_name);
+        let mut flags = [0; SIZE];
+        if let Some(saved) = file.as_ref().and_then(|file| std::fs::read(file).ok()) {
+            let size = saved.len().min(SIZE);
+            flags[..size].copy_from_slice(&saved[..size]);
+        }
+        RplFlags { flags, file }
+    }
+
+    pub fn get(&self) -> &[u8; SIZE] {
+        &self.flags
+    }
+
+    // Sets the first flags and writes them all through to the file
+    pub fn store(&mut self, values: &[u8]) -> Result<()> {
+        self.flags[..values.len()].copy_from_slice(values);
+        if let Some(file) = &self.file {
+            if let Some(directory) = file.parent() {
+                std::fs::create_dir_all(directory)?;
+            }
+            std::fs::write(file, self.flags)?;
+        }
+        Ok(())
+    }
+}
+
+// $XDG_DATA_HOME/ivsemu/flags, or ~/.local/share/ivsemu/flags when it is not set
+pub fn data_dir() -> Option<PathBuf> {
+    let base = match std::env::var_os("XDG_DATA_HOME").filter(|dir| !dir.is_empty()) {
+        Some(dir) => PathBuf::from(dir),
+        None => PathBuf::from(std::env::var_os("HOME")?).join(".local").join("share"),
+    };
+    Some(base.join("ivsemu").join("flags"))
+}
+
+fn flags_file(rom_name: &str) -> Option<PathBuf> {
+    match rom_name {
+        "" | "-" => None,
+        _ => Some(data_dir()?.join(format!("{}{}", rom_name, EXTENSION))),
+    }
+}
+
+// Every ROM with saved flags and its flags, sorted by name
+pub fn list() -> Vec<(String, [u8; SIZE])> {
+    let read_dir = match data_dir().map(std::fs::read_dir) {
+        Some(Ok(read_dir)) => read_dir,
+        _ => return vec![],
+    };
+    let mut roms: Vec<(String, [u8; SIZE])> = read_dir
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| entry.file_name().to_str()?.strip_suffix(EXTENSION).map(str::to_string))
+        .map(|rom_name| {
+            let flags = *RplFlags::open(&rom_name).get();
+            (rom_name, flags)
+        })
+        .collect();
+    roms.sort_by(|(a, _), (b, _)| a.cmp(b));
+    roms
+}
+
+// Deletes the saved flags of a ROM, or of every ROM for "all", returning the ROMs cleared
+pub fn clear(rom_name: &str) -> Result<Vec<String>> {
+    let roms: Vec<String> = match rom_name {
+        "all" => list().into_iter().map(|(rom_name, _)| rom_name).collect(),
+        _ => vec![rom_name.to_string()],
+    };
+    let mut cleared = vec![];
+    for rom_name in roms {
+        if let Some(file) = flags_file(&rom_name).filter(|file| file.exists()) {
+            std::fs::remove_file(file)?;
+            cleared.push(rom_name);
+        }
+    }
+    Ok(cleared)
+}
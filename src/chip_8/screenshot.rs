@@ -0,0 +1,61 @@
+use super::cpu::cpu::CPU;
+use super::display::{HEIGHT, WIDTH};
+use super::image;
+
+use std::io::Result;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Writes <dir>/ivsemu-<timestamp>.png, -x<scale>.png and .pbm, returning the base path
+pub fn capture(cpu: &CPU, scale: u32, directory: &str) -> Result<String> {
+    std::fs::create_dir_all(directory)?;
+    let base = Path::new(directory)
+        .join(format!("ivsemu-{}", timestamp()))
+        .to_string_lossy()
+        .into_owned();
+    let rgba = cpu.get_frame_buffer();
+
+    image::write_png(&format!("{}.png", base), WIDTH, HEIGHT, rgba)?;
+    if scale > 1 {
+        let scaled = image::scale_rgba(rgba, WIDTH, HEIGHT, scale as usize);
+        image::write_png(
+            &format!("{}-x{}.png", base, scale),
+            WIDTH * scale as usize,
+            HEIGHT * scale as usize,
+            &scaled,
+        )?;
+    }
+    image::write_pbm(&format!("{}.pbm", base), WIDTH, HEIGHT, &cpu.get_pixels())?;
+    Ok(base)
+}
+
+// UTC "YYYYMMDD-HHMMSS-mmm"
+pub fn timestamp() -> String {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    let secs = now.as_secs();
+    let (hours, minutes, seconds) = ((secs / 3600) % 24, (secs / 60) % 60, secs % 60);
+
+    // Days since the epoch to a civil date (Howard Hinnant's algorithm)
+    let z = (secs / 86400) as i64 + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + (month <= 2) as i64;
+
+    format!(
+        "{:04}{:02}{:02}-{:02}{:02}{:02}-{:03}",
+        year,
+        month,
+        day,
+        hours,
+        minutes,
+        seconds,
+        now.subsec_millis()
+    )
+}
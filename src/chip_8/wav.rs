@@ -0,0 +1,72 @@
+use std::fs::File;
+use std::io::{BufWriter, Result, Write};
+
+// 16-bit mono PCM
+pub fn write_wav(filename: &str, sample_rate: u32, samples: &[f32]) -> Result<()> {
+    let mut f = BufWriter::new(File::create(filename)?);
+    write_samples(&mut f, sample_rate, samples)?;
+    f.flush()
+}
+
+fn write_samples<W: Write>(f: &mut W, sample_rate: u32, samples: &[f32]) -> Result<()> {
+    let data_size = samples.len() as u32 * 2;
+    f.write_all(b"RIFF")?;
+    f.write_all(&(36 + data_size).to_le_bytes())?;
+    f.write_all(b"WAVE")?;
+    f.write_all(b"fmt ")?;
+    f.write_all(&16u32.to_le_bytes())?; // fmt chunk size
+    f.write_all(&1u16.to_le_bytes())?; // PCM
+    f.write_all(&1u16.to_le_bytes())?; // Channels
+    f.write_all(&sample_rate.to_le_bytes())?;
+    f.write_all(&(sample_rate * 2).to_le_bytes())?; // Byte rate
+    f.write_all(&2u16.to_le_bytes())?; // Block align
+    f.write_all(&16u16.to_le_bytes())?; // Bits per sample
+    f.write_all(b"data")?;
+    f.write_all(&data_size.to_le_bytes())?;
+    for sample in samples {
+        let value = (sample.clamp(-1., 1.) * i16::MAX as f32) as i16;
+        f.write_all(&value.to_le_bytes())?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::TryInto;
+    use std::io::Cursor;
+
+    fn u16_at(bytes: &[u8], offset: usize) -> u16 {
+        u16::from_le_bytes(bytes[offset..offset + 2].try_into().unwrap())
+    }
+
+    fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+        u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+    }
+
+    #[test]
+    fn header_and_samples() {
+        let samples = [0., 1., -1., 0.5, 2.];
+        let mut cursor = Cursor::new(vec![]);
+        write_samples(&mut cursor, 44100, &samples).unwrap();
+        let bytes = cursor.into_inner();
+
+        assert_eq!(bytes.len(), 44 + samples.len() * 2);
+        assert_eq!(&bytes[0..4], b"RIFF");
+        assert_eq!(u32_at(&bytes, 4), 36 + 10);
+        assert_eq!(&bytes[8..16], b"WAVEfmt ");
+        assert_eq!(u32_at(&bytes, 16), 16);
+        assert_eq!(u16_at(&bytes, 20), 1);
+        assert_eq!(u16_at(&bytes, 22), 1);
+        assert_eq!(u32_at(&bytes, 24), 44100);
+        assert_eq!(u32_at(&bytes, 28), 88200);
+        assert_eq!(u16_at(&bytes, 32), 2);
+        assert_eq!(u16_at(&bytes, 34), 16);
+        assert_eq!(&bytes[36..40], b"data");
+        assert_eq!(u32_at(&bytes, 40), 10);
+
+        // Out of range samples are clamped
+        let values: Vec<i16> = bytes[44..].chunks(2).map(|b| i16::from_le_bytes([b[0], b[1]])).collect();
+        assert_eq!(values, [0, i16::MAX, -i16::MAX, i16::MAX / 2, i16::MAX]);
+    }
+}